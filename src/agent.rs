@@ -0,0 +1,269 @@
+use crate::entry::Entry;
+use crate::pwd::zero_memory;
+use crate::utils::{self, Source};
+use crate::{Args, Result, BIN_NAME, CANCEL, CANCEL_RQ_FREQ};
+
+use once_cell::unsync::OnceCell;
+use serde_derive::{Deserialize, Serialize};
+
+use log::*;
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long databases stay unlocked since the last request
+const DEFAULT_IDLE: Duration = Duration::from_secs(15 * 60);
+/// Neither side waits longer for the other
+const IO_TIMEOUT: Duration = Duration::from_secs(5);
+/// Requests are lists of paths, anything larger is not one
+const MAX_REQUEST: u64 = 1 << 20;
+
+/// Database file and key file unlocking it, both canonical.
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Key {
+    path: PathBuf,
+    key_file: Option<PathBuf>,
+}
+
+impl Key {
+    fn new(source: &Source) -> Result<Self> {
+        let canonical = |path: &Path| {
+            path.canonicalize()
+                .map_err(|e| format!("Can't open `{}`. {}", path.display(), e))
+        };
+
+        Ok(Key {
+            path: canonical(&source.path)?,
+            key_file: source.key_file.as_deref().map(canonical).transpose()?,
+        })
+    }
+}
+
+/// Database entries kept by the agent.
+struct Unlocked {
+    key: Key,
+    /// Changed files are read by the commands themselves
+    modified: SystemTime,
+    entries: Vec<Entry>,
+}
+
+/// Unlocks the databases once and serves their entries to other commands.
+///
+/// Entries are copied out of the database (see [`Entry`]), so the KDF runs once
+/// per agent rather than once per command. Only processes of the same user
+/// are answered, as told by the kernel.
+pub(super) fn run(args: Args) -> Result<()> {
+    let idle = args
+        .flag_agent_timeout
+        .as_deref()
+        .map(utils::parse_duration)
+        .transpose()?
+        .unwrap_or(DEFAULT_IDLE);
+
+    let socket = socket_path(true)?;
+
+    if UnixStream::connect(&socket).is_ok() {
+        return Err(format!("Agent is already running on `{}`.", socket.display()).into());
+    }
+
+    // Left by a killed agent
+    let _ = fs::remove_file(&socket);
+
+    // Pipe or descriptor can be read only once, so the password is shared among databases
+    let piped = OnceCell::new();
+
+    let dbs = args
+        .databases
+        .iter()
+        .map(|src| {
+            let key = Key::new(src)?;
+            let modified = fs::metadata(&key.path)?.modified()?;
+            let entries = utils::unlock(src, &piped)?;
+
+            // Replies carry passwords, the database stays here
+            entries.iter().for_each(Entry::copy_password);
+
+            Ok(Unlocked {
+                key,
+                modified,
+                entries,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Never readable by others, not even for a moment
+    let mask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&socket);
+    unsafe { libc::umask(mask) };
+
+    let listener =
+        listener.map_err(|e| format!("Can't listen on `{}`. {}", socket.display(), e))?;
+    listener.set_nonblocking(true)?;
+
+    wout!("Agent is listening on {}", socket.display());
+
+    let mut deadline = Instant::now() + idle;
+
+    while !CANCEL.load(atomic::Ordering::SeqCst) && Instant::now() < deadline {
+        match listener.accept() {
+            Ok((stream, _)) => match serve(stream, &dbs) {
+                Ok(_) => deadline = Instant::now() + idle,
+                Err(e) => warn!("request refused: {}", e),
+            },
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(1_000 / CANCEL_RQ_FREQ))
+            }
+            Err(e) => warn!("can't accept a request: {}", e),
+        }
+    }
+
+    debug!("locking databases");
+    let _ = fs::remove_file(&socket);
+
+    Ok(())
+}
+
+fn serve(mut stream: UnixStream, dbs: &[Unlocked]) -> Result<()> {
+    // Inherited from the listener on some systems
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let uid = peer_uid(&stream)?;
+    if uid != unsafe { libc::getuid() } {
+        return Err(format!("peer is user {}", uid).into());
+    }
+
+    let mut request = Vec::new();
+    (&stream).take(MAX_REQUEST).read_to_end(&mut request)?;
+    let keys: Vec<Key> = serde_json::from_slice(&request)?;
+
+    let found = keys
+        .iter()
+        .map(|key| {
+            let db = dbs.iter().find(|db| db.key == *key)?;
+            let modified = fs::metadata(&key.path).and_then(|m| m.modified()).ok();
+
+            if modified != Some(db.modified) {
+                debug!("changed since unlocked: {}", key.path.display());
+                return None;
+            }
+
+            Some(&db.entries)
+        })
+        .collect::<Vec<_>>();
+
+    let mut response = serde_json::to_vec(&found)?;
+    let sent = stream.write_all(&response);
+    zero_memory(&mut response);
+
+    Ok(sent?)
+}
+
+/// Entries of the databases the agent has unlocked, `None` for the rest.
+pub fn fetch(sources: &[Source]) -> Vec<Option<Vec<Entry>>> {
+    match request(sources) {
+        Ok(found) if found.len() == sources.len() => found,
+        Ok(_) => {
+            warn!("agent answered for other databases");
+            sources.iter().map(|_| None).collect()
+        }
+        Err(e) => {
+            debug!("no agent: {}", e);
+            sources.iter().map(|_| None).collect()
+        }
+    }
+}
+
+fn request(sources: &[Source]) -> Result<Vec<Option<Vec<Entry>>>> {
+    let keys = sources.iter().map(Key::new).collect::<Result<Vec<_>>>()?;
+
+    let mut stream = UnixStream::connect(socket_path(false)?)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    // Anybody may bind a socket in /tmp
+    let uid = peer_uid(&stream)?;
+    if uid != unsafe { libc::getuid() } {
+        return Err(format!("agent is run by user {}", uid).into());
+    }
+
+    stream.write_all(&serde_json::to_vec(&keys)?)?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = Vec::new();
+    let read = stream.read_to_end(&mut response);
+    let found = read
+        .map_err(From::from)
+        .and_then(|_| serde_json::from_slice(&response).map_err(From::from));
+    zero_memory(&mut response);
+
+    found
+}
+
+/// `$XDG_RUNTIME_DIR/kp-agent.sock` or the same in a private directory of `/tmp`.
+fn socket_path(create: bool) -> Result<PathBuf> {
+    let name = format!("{}-agent.sock", BIN_NAME);
+
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir).join(name));
+    }
+
+    let uid = unsafe { libc::getuid() };
+    let dir = env::temp_dir().join(format!("{}-{}", BIN_NAME, uid));
+
+    if create {
+        // Fails if it exists, which is checked below
+        let _ = fs::DirBuilder::new().mode(0o700).create(&dir);
+    }
+
+    // Anybody could have created it first
+    let meta = fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return Err(format!("`{}` is not a private directory.", dir.display()).into());
+    }
+
+    Ok(dir.join(name))
+}
+
+/// User on the other end of the socket, as told by the kernel.
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut cred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+
+    match res {
+        0 => Ok(cred.uid),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// User on the other end of the socket, as told by the kernel.
+#[cfg(not(target_os = "linux"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let (mut uid, mut gid) = (0, 0);
+
+    match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+        0 => Ok(uid),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
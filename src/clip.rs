@@ -1,4 +1,6 @@
-use crate::{utils::*, Args, Result, CANCEL, CANCEL_RQ_FREQ};
+use crate::{
+    entry::Entry, pwd::Pwd, resolve::Resolver, utils::*, Args, Result, CANCEL, CANCEL_RQ_FREQ,
+};
 
 use log::*;
 
@@ -7,25 +9,29 @@ use std::thread;
 use std::time;
 
 pub(super) fn run(args: Args) -> Result<()> {
-    let db = open_database(
-        args.flag_database.as_deref().unwrap(),
-        args.flag_key_file.as_deref(),
-        args.flag_use_keyring,
-    )?;
+    let dbs = open_databases(&args.databases)?;
+    let entries = Entries::new(&dbs, &args.databases);
 
     let query = args.arg_entry.as_ref().map(String::as_ref);
 
-    if let Some(query) = query {
-        if let [entry] = db.find(query).as_slice() {
-            // Print password to stdout when pipe used
-            // e.g. `kp clip example.com | cat`
-            if !is_tty(io::stdout()) {
-                put!("{}", entry.password()?);
-                return Ok(());
-            }
+    // Only a single title or exact URL match is taken without the user seeing it
+    let found = match query {
+        Some(query) => entries.search_unattended(query, args.flag_url)?,
+        None => Vec::new(),
+    };
 
-            return clip(entry, args.flag_timeout);
+    if let [(db, entry)] = found.as_slice() {
+        let resolver = Resolver::new(entries.of(*db));
+        let pwd = Pwd::from(resolver.field(entry, args.flag_field.as_deref())?);
+
+        // Print password to stdout when pipe used
+        // e.g. `kp clip example.com | cat`
+        if !is_tty(io::stdout()) {
+            put!("{}", &*pwd);
+            return Ok(());
         }
+
+        return clip(entry, &pwd, &args);
     }
 
     // If more than a single match has been found and stdout is not a TTY
@@ -34,36 +40,70 @@ pub(super) fn run(args: Args) -> Result<()> {
         return Err(format!("No single match for {}.", query.unwrap_or("[empty]")).into());
     }
 
-    if let Some(entry) = skim(
-        &db.entries(),
+    // URL matches can't be narrowed down by typing, so offer only them, wider ones included
+    let (items, query) = match (args.flag_url, query) {
+        (true, Some(url)) => match entries.search(url, true)? {
+            found if found.is_empty() => return Err(format!("No match for {}.", url).into()),
+            found => (found, None),
+        },
+        _ => (entries.iter().collect(), query),
+    };
+
+    if let Some((db, entry)) = skim(
+        &entries,
+        items,
         query,
         args.flag_no_group,
         args.flag_preview,
         args.flag_full_screen,
     ) {
-        clip(entry, args.flag_timeout)?
+        let resolver = Resolver::new(entries.of(db));
+        let pwd = Pwd::from(resolver.field(entry, args.flag_field.as_deref())?);
+        clip(entry, &pwd, &args)?
     }
 
     Ok(())
 }
 
-fn clip<'a>(entry: &'a kdbx4::Entry<'a>, timeout: Option<u8>) -> Result<()> {
-    let pwd = entry.password()?;
-
-    if set_clipboard(Some(pwd)).is_err() {
-        return Err(format!(
+fn clip(entry: &Entry, pwd: &str, args: &Args) -> Result<()> {
+    copy(pwd, args.flag_timeout.map(u64::from), args).map_err(|_| {
+        format!(
             "Clipboard unavailable. Try use STDOUT, i.e. `kp clip '{}' | cat`.",
             entry.title()
         )
-        .into());
-    }
+        .into()
+    })
+}
+
+/// Puts the value to the clipboard and wipes it out after the timeout
+/// bringing back what was copied before.
+///
+/// Fails only when the clipboard is unavailable.
+pub(super) fn copy(val: &str, timeout: Option<u64>, args: &Args) -> Result<()> {
+    let backend = args.clipboard;
 
     if timeout.is_none() {
         debug!("user decided to leave the password in the buffer");
-        return Ok(());
+        return backend.set(Some(val));
+    }
+
+    backend.copy(val)?;
+
+    if args.flag_background {
+        match backend.restore_in_background(timeout.unwrap()) {
+            Ok(_) => {
+                wout!(
+                    "Copied to the clipboard! Clear in {} seconds.",
+                    timeout.unwrap()
+                );
+                return Ok(());
+            }
+            // Still better to wait than to leave the secret
+            Err(e) => warn!("can't start background wipe: {}", e),
+        }
     }
 
-    let mut ticks = u64::from(timeout.unwrap()) * CANCEL_RQ_FREQ;
+    let mut ticks = timeout.unwrap() * CANCEL_RQ_FREQ;
     while !CANCEL.load(std::sync::atomic::Ordering::SeqCst) && ticks > 0 {
         if ticks % CANCEL_RQ_FREQ == 0 {
             // Note extra space after the "seconds...":
@@ -79,7 +119,7 @@ fn clip<'a>(entry: &'a kdbx4::Entry<'a>, timeout: Option<u8>) -> Result<()> {
         ticks -= 1;
     }
 
-    let _ = set_clipboard(None);
+    let _ = backend.restore();
     wout!("{:50}", "Wiped out");
 
     Ok(())
@@ -0,0 +1,556 @@
+use crate::pwd::Pwd;
+use crate::utils::{find_program, is_tty};
+use crate::Result;
+
+#[cfg(feature = "clipboard")]
+use ::clipboard::{ClipboardContext, ClipboardProvider};
+
+#[cfg(all(feature = "clipboard", target_os = "linux"))]
+mod x11;
+
+#[cfg(all(feature = "clipboard", target_os = "macos"))]
+mod macos;
+
+use log::*;
+
+use sha2::{Digest, Sha256};
+
+use std::env;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{self, Child, Command, Stdio};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// Named, so clearing doesn't drop what the user copied in tmux meanwhile
+const TMUX_BUFFER: &str = crate::BIN_NAME;
+
+/// Secret on the clipboard and what it replaced, shared with Ctrl+C handler.
+static COPIED: Mutex<Option<Copied>> = Mutex::new(None);
+
+/// Set for the process started by [`Backend::restore_in_background`].
+pub static WIPER_VAR_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "_BACKGROUND_WIPE");
+
+/// Set for the process owning X11 selection, see [`run_server`].
+pub static SERVER_VAR_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "_CLIPBOARD_SERVER");
+
+struct Copied {
+    /// Enough to tell whether the secret is still there
+    digest: String,
+    previous: Option<Pwd>,
+}
+
+/// Destination of copied secrets.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// macOS pasteboard or X11 selection, which on Linux is served by a detached `kp`
+    #[default]
+    Native,
+    /// `wl-copy` of wl-clipboard
+    Wayland,
+    Xclip,
+    Xsel,
+    /// Terminal escape sequence, works over SSH in terminals supporting it
+    Osc52,
+    /// `tmux load-buffer`, also forwarded to the terminal's clipboard by tmux 3.2+
+    Tmux,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        match name {
+            "native" => Ok(Backend::Native),
+            "wayland" => Ok(Backend::Wayland),
+            "xclip" => Ok(Backend::Xclip),
+            "xsel" => Ok(Backend::Xsel),
+            "osc52" => Ok(Backend::Osc52),
+            "tmux" => Ok(Backend::Tmux),
+            _ => Err(format!(
+                "Unknown clipboard `{}`. Use `native`, `wayland`, `xclip`, `xsel`, `osc52` or `tmux`.",
+                name
+            )),
+        }
+    }
+}
+
+impl Backend {
+    /// Wayland, then X11, then tmux and finally the terminal of an SSH session.
+    pub fn detect() -> Self {
+        let has_var = |var| env::var_os(var).is_some_and(|v| !v.is_empty());
+
+        let backend = if cfg!(target_os = "macos") {
+            Backend::Native
+        } else if has_var("WAYLAND_DISPLAY") && find_program("wl-copy").is_some() {
+            Backend::Wayland
+        } else if has_var("DISPLAY") && cfg!(feature = "clipboard") {
+            // Unlike the tools it marks secrets for clipboard managers
+            Backend::Native
+        } else if has_var("DISPLAY") && find_program("xclip").is_some() {
+            Backend::Xclip
+        } else if has_var("DISPLAY") && find_program("xsel").is_some() {
+            Backend::Xsel
+        } else if has_var("DISPLAY") {
+            Backend::Native
+        } else if has_var("TMUX") {
+            Backend::Tmux
+        } else if has_var("SSH_TTY") {
+            Backend::Osc52
+        } else {
+            Backend::Native
+        };
+
+        debug!("clipboard backend: {:?}", backend);
+        backend
+    }
+
+    /// Puts the secret to the clipboard remembering what was there for [`Backend::restore`].
+    pub fn copy(&self, secret: &str) -> Result<()> {
+        let previous = self.get().ok().filter(|p| !p.is_empty());
+        self.set(Some(secret))?;
+
+        *copied() = Some(Copied {
+            digest: digest(secret),
+            previous,
+        });
+
+        Ok(())
+    }
+
+    /// Puts back what the secret replaced, unless the user has copied something else since.
+    ///
+    /// Write-only clipboards (OSC 52) are cleared blindly.
+    pub fn restore(&self) -> Result<()> {
+        // Held until restored, so Ctrl+C handler doesn't exit amid it
+        let mut guard = copied();
+
+        let copied = match guard.take() {
+            Some(copied) => copied,
+            None => return Ok(()),
+        };
+
+        if !self.is_write_only() {
+            // Anything unreadable, e.g. an image, was copied by the user too
+            let unchanged = self
+                .get()
+                .map(|current| digest(&current) == copied.digest)
+                .unwrap_or_else(|e| {
+                    debug!("can't read the clipboard: {}", e);
+                    false
+                });
+
+            if !unchanged {
+                debug!("clipboard has changed, leaving it as it is");
+
+                // Not what tmux pastes anymore, but still listed with the secret
+                if *self == Backend::Tmux {
+                    run("tmux", &["delete-buffer", "-b", TMUX_BUFFER], None)?;
+                }
+
+                return Ok(());
+            }
+        }
+
+        // Better an empty clipboard than the secret left on it
+        self.put(copied.previous.as_deref(), false)
+            .or_else(|e| match copied.previous {
+                Some(_) => self.put(None, false),
+                None => Err(e),
+            })
+    }
+
+    /// Passes restoring to a detached process, so it happens even if the terminal is closed.
+    pub fn restore_in_background(&self, timeout: u64) -> Result<()> {
+        if !self.outlives_process() {
+            return Err(format!("Clipboard `{}` is cleared when kp exits.", self).into());
+        }
+
+        let mut guard = copied();
+
+        let copied = match guard.as_ref() {
+            Some(copied) => copied,
+            None => return Ok(()),
+        };
+
+        // Terminal's clipboard is reachable only through the inherited descriptor
+        let stdout = match self {
+            Backend::Osc52 => OpenOptions::new().write(true).open("/dev/tty")?.into(),
+            _ => Stdio::null(),
+        };
+
+        let mut child = spawn_detached(WIPER_VAR_NAME, stdout)?;
+
+        let job = Pwd::from(format!(
+            "{}\n{}\n{}\n{}",
+            self,
+            timeout,
+            copied.digest,
+            copied.previous.as_deref().unwrap_or_default()
+        ));
+
+        child
+            .stdin
+            .take()
+            .expect("piped stdin")
+            .write_all(job.as_bytes())?;
+
+        // It's the wiper's job now
+        *guard = None;
+
+        Ok(())
+    }
+
+    /// Whether the copied value is still there once kp exits, so the wiper has something to wipe.
+    fn outlives_process(&self) -> bool {
+        match self {
+            // Pasteboard is kept by the system, X11 selection by a detached `kp`
+            Backend::Native => cfg!(any(
+                target_os = "macos",
+                all(feature = "clipboard", target_os = "linux")
+            )),
+            // Served by a forked process or kept by tmux or the terminal
+            Backend::Wayland | Backend::Xclip | Backend::Xsel | Backend::Osc52 | Backend::Tmux => {
+                true
+            }
+        }
+    }
+
+    /// Clipboard that can't be read back, so there is no telling whether the secret is still there.
+    fn is_write_only(&self) -> bool {
+        match self {
+            Backend::Osc52 => true,
+            Backend::Native | Backend::Wayland | Backend::Xclip | Backend::Xsel | Backend::Tmux => {
+                false
+            }
+        }
+    }
+
+    /// Current contents of the clipboard.
+    pub fn get(&self) -> Result<Pwd> {
+        match self {
+            Backend::Native => get_native(),
+            Backend::Wayland => output("wl-paste", &["--no-newline"]),
+            Backend::Xclip => output("xclip", &["-selection", "clipboard", "-out"]),
+            Backend::Xsel => output("xsel", &["--clipboard", "--output"]),
+            Backend::Osc52 => Err("Terminal's clipboard can't be read.".into()),
+            // The most recent buffer is what tmux pastes
+            Backend::Tmux => output("tmux", &["show-buffer"]),
+        }
+    }
+
+    /// Puts the secret to the clipboard, `None` clears it.
+    pub fn set(&self, val: Option<&str>) -> Result<()> {
+        self.put(val, true)
+    }
+
+    /// Only the native clipboard can tell managers not to keep a secret in their history.
+    fn put(&self, val: Option<&str>, secret: bool) -> Result<()> {
+        match self {
+            Backend::Native => set_native(val, secret),
+            Backend::Wayland => match val {
+                Some(val) => run("wl-copy", &[], Some(val)),
+                None => run("wl-copy", &["--clear"], None),
+            },
+            Backend::Xclip => run(
+                "xclip",
+                &["-selection", "clipboard", "-in"],
+                Some(val.unwrap_or_default()),
+            ),
+            Backend::Xsel => match val {
+                Some(val) => run("xsel", &["--clipboard", "--input"], Some(val)),
+                None => run("xsel", &["--clipboard", "--clear"], None),
+            },
+            Backend::Osc52 => osc52(val.unwrap_or_default()),
+            Backend::Tmux => match val {
+                Some(val) => run(
+                    "tmux",
+                    &["load-buffer", "-b", TMUX_BUFFER, "-w", "-"],
+                    Some(val),
+                )
+                // `-w` is unknown before tmux 3.2
+                .or_else(|_| run("tmux", &["load-buffer", "-b", TMUX_BUFFER, "-"], Some(val))),
+                None => clear_tmux(),
+            },
+        }
+        .map_err(|e| {
+            warn!("could not set the clipboard: {}", e);
+            e
+        })
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Backend::Native => "native",
+            Backend::Wayland => "wayland",
+            Backend::Xclip => "xclip",
+            Backend::Xsel => "xsel",
+            Backend::Osc52 => "osc52",
+            Backend::Tmux => "tmux",
+        })
+    }
+}
+
+/// Body of the process started by [`Backend::restore_in_background`].
+pub fn run_wiper() -> ! {
+    let result = read_job().and_then(|(backend, timeout)| {
+        thread::sleep(Duration::from_secs(timeout));
+        backend.restore()
+    });
+
+    if let Err(e) = result {
+        warn!("background wipe failed: {}", e);
+        process::exit(1);
+    }
+
+    process::exit(0)
+}
+
+/// Takes backend, timeout, digest of the secret and previous contents from STDIN.
+fn read_job() -> Result<(Backend, u64)> {
+    let mut job = String::new();
+    io::stdin().read_to_string(&mut job)?;
+    let job = Pwd::from(job);
+
+    let mut lines = job.splitn(4, '\n');
+    let mut next = || lines.next().ok_or("Incomplete wipe job.");
+
+    let backend = next()?.parse::<Backend>()?;
+    let timeout = next()?.parse()?;
+    let digest = next()?.to_owned();
+    let previous = Some(next()?)
+        .filter(|p| !p.is_empty())
+        .map(|p| Pwd::from(p.to_owned()));
+
+    *copied() = Some(Copied { digest, previous });
+
+    Ok((backend, timeout))
+}
+
+fn digest(val: &str) -> String {
+    Sha256::digest(val.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(feature = "clipboard")]
+fn set_native(val: Option<&str>, secret: bool) -> Result<()> {
+    // X11 selection lives as long as its owner, so it's served by a detached process
+    #[cfg(target_os = "linux")]
+    return match val {
+        Some(val) => serve(val, secret),
+        None => x11::clear(),
+    };
+
+    #[cfg(target_os = "macos")]
+    if let (Some(val), true) = (val, secret) {
+        return macos::set_secret(val);
+    }
+
+    #[allow(unreachable_code)]
+    ClipboardProvider::new()
+        .and_then(|mut ctx: ClipboardContext| ctx.set_contents(val.unwrap_or_default().to_owned()))
+}
+
+/// Starts the process owning the selection and waits until it does.
+#[cfg(all(feature = "clipboard", target_os = "linux"))]
+fn serve(val: &str, secret: bool) -> Result<()> {
+    use std::io::BufRead;
+
+    let mut child = spawn_detached(SERVER_VAR_NAME, Stdio::piped())?;
+
+    let job = Pwd::from(format!("{}\n{}", secret, val));
+
+    child
+        .stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(job.as_bytes())?;
+
+    // A line once owned, nothing if it failed
+    let mut ready = String::new();
+    io::BufReader::new(child.stdout.take().expect("piped stdout")).read_line(&mut ready)?;
+
+    match ready.as_str() {
+        "\n" => Ok(()),
+        _ => Err("Can't own the clipboard.".into()),
+    }
+}
+
+/// Body of the process started by `serve`, exits once something else is copied.
+pub fn run_server() -> ! {
+    #[cfg(all(feature = "clipboard", target_os = "linux"))]
+    {
+        let result = read_value().and_then(|(val, secret)| {
+            let selection = x11::Selection::own()?;
+
+            println!();
+            io::stdout().flush()?;
+
+            selection.serve(&val, secret);
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            warn!("serving the clipboard failed: {}", e);
+            process::exit(1);
+        }
+
+        process::exit(0)
+    }
+
+    #[allow(unreachable_code)]
+    process::exit(1)
+}
+
+#[cfg(all(feature = "clipboard", target_os = "linux"))]
+fn read_value() -> Result<(Pwd, bool)> {
+    let mut job = String::new();
+    io::stdin().read_to_string(&mut job)?;
+    let job = Pwd::from(job);
+
+    let (secret, val) = job.split_once('\n').ok_or("Incomplete clipboard job.")?;
+
+    Ok((Pwd::from(val.to_owned()), secret.parse()?))
+}
+
+#[cfg(feature = "clipboard")]
+fn get_native() -> Result<Pwd> {
+    ClipboardProvider::new()
+        .and_then(|mut ctx: ClipboardContext| ctx.get_contents())
+        .map(Pwd::from)
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn set_native(_: Option<&str>, _: bool) -> Result<()> {
+    Err("Feature clipboard is not available.".into())
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn get_native() -> Result<Pwd> {
+    Err("Feature clipboard is not available.".into())
+}
+
+/// Runs `kp` itself in the role given by the variable, fed through STDIN.
+///
+/// A fresh process rather than `fork` as clipboard APIs (e.g. macOS, xcb) don't survive it.
+fn spawn_detached(var: &str, stdout: Stdio) -> Result<Child> {
+    let child = unsafe {
+        Command::new(env::current_exe()?)
+            // The wiper restoring the native clipboard starts a server, which must not be a wiper too
+            .env_remove(WIPER_VAR_NAME.to_uppercase())
+            .env_remove(SERVER_VAR_NAME.to_uppercase())
+            .env(var.to_uppercase(), "1")
+            .stdin(Stdio::piped())
+            .stdout(stdout)
+            .stderr(Stdio::null())
+            // New session without controlling terminal, so no SIGHUP when it's closed
+            .pre_exec(|| match libc::setsid() {
+                -1 => Err(io::Error::last_os_error()),
+                _ => Ok(()),
+            })
+            .spawn()?
+    };
+
+    Ok(child)
+}
+
+fn copied() -> std::sync::MutexGuard<'static, Option<Copied>> {
+    COPIED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Runs the program passing the value through STDIN, never as an argument visible in `ps`.
+fn run(program: &str, args: &[&str], input: Option<&str>) -> Result<()> {
+    // Copying tools fork to serve the selection, so their output is never waited for
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Can't run `{}`. {}", program, e))?;
+
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        stdin.write_all(input.as_bytes())?;
+    }
+
+    match child.wait()? {
+        status if status.success() => Ok(()),
+        status => Err(format!("`{}` failed with {}.", program, status).into()),
+    }
+}
+
+fn output(program: &str, args: &[&str]) -> Result<Pwd> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|e| format!("Can't run `{}`. {}", program, e))?;
+
+    // Empty clipboard is an error for most of the tools
+    if !output.status.success() {
+        return Err(format!("`{}` failed with {}.", program, output.status).into());
+    }
+
+    String::from_utf8(output.stdout)
+        .map(Pwd::from)
+        .map_err(|e| e.to_string().into())
+}
+
+/// Deletes the buffer and empties the terminal's clipboard set by `load-buffer -w`.
+///
+/// Loading empty input leaves both as they are, so the client's terminal gets
+/// an empty OSC 52 directly, same as tmux sends it.
+fn clear_tmux() -> Result<()> {
+    let forwarded = output("tmux", &["show-options", "-gv", "set-clipboard"])
+        .map(|opt| opt.trim() != "off")
+        .unwrap_or(true);
+
+    let client = output("tmux", &["display-message", "-p", "#{client_tty}"]);
+
+    match client.as_deref().map(str::trim) {
+        Ok(tty) if forwarded && !tty.is_empty() => {
+            OpenOptions::new()
+                .write(true)
+                .open(tty)
+                .and_then(|mut tty| tty.write_all(b"\x1b]52;c;\x07"))
+                .unwrap_or_else(|e| warn!("can't clear terminal's clipboard: {}", e));
+        }
+        _ => debug!("no tmux client to clear terminal's clipboard of"),
+    }
+
+    run("tmux", &["delete-buffer", "-b", TMUX_BUFFER], None)
+}
+
+/// See "Operating System Commands" in xterm's ctlseqs, `c` is the clipboard selection.
+fn osc52(val: &str) -> Result<()> {
+    let data = Pwd::from(base64::encode(val));
+
+    // Let the sequence through tmux to the outer terminal
+    let seq = Pwd::from(match env::var_os("TMUX") {
+        Some(_) => format!("\x1bPtmux;\x1b\x1b]52;c;{}\x07\x1b\\", &*data),
+        None => format!("\x1b]52;c;{}\x07", &*data),
+    });
+
+    // Background wiper has no controlling terminal, it gets the terminal as STDOUT
+    let mut tty: Box<dyn Write> = match OpenOptions::new().write(true).open("/dev/tty") {
+        Ok(tty) => Box::new(tty),
+        Err(_) if is_tty(io::stdout()) => Box::new(io::stdout()),
+        Err(e) => return Err(e.into()),
+    };
+
+    tty.write_all(seq.as_bytes())?;
+    tty.flush().map_err(From::from)
+}
@@ -0,0 +1,88 @@
+use crate::Result;
+
+use std::ffi::{c_void, CString};
+use std::mem;
+use std::os::raw::{c_char, c_ulong};
+
+type Id = *mut c_void;
+type Sel = *const c_void;
+
+// NSPasteboardTypeString
+const STRING_TYPE: &str = "public.utf8-plain-text";
+// See nspasteboard.org, honoured by Alfred, Maccy, Paste, etc.
+const CONCEALED_TYPE: &str = "org.nspasteboard.ConcealedType";
+const NS_UTF8_STRING_ENCODING: c_ulong = 4;
+
+#[link(name = "AppKit", kind = "framework")]
+extern "C" {}
+
+#[link(name = "objc")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> Id;
+    fn sel_registerName(name: *const c_char) -> Sel;
+    fn objc_msgSend();
+}
+
+/// Puts the secret to the general pasteboard marking it as concealed for clipboard managers.
+///
+/// `clipboard` crate writes a plain string only.
+pub fn set_secret(val: &str) -> Result<()> {
+    unsafe {
+        let pool = send(class("NSAutoreleasePool"), "new");
+        let pasteboard = send(class("NSPasteboard"), "generalPasteboard");
+
+        send(pasteboard, "clearContents");
+
+        let done =
+            set_string(pasteboard, val, STRING_TYPE) && set_string(pasteboard, "", CONCEALED_TYPE);
+
+        send(pool, "drain");
+
+        match done {
+            true => Ok(()),
+            false => Err("Can't write to the pasteboard.".into()),
+        }
+    }
+}
+
+unsafe fn set_string(pasteboard: Id, val: &str, kind: &str) -> bool {
+    let msg: extern "C" fn(Id, Sel, Id, Id) -> i8 = mem::transmute(objc_msgSend as *const c_void);
+
+    msg(
+        pasteboard,
+        sel("setString:forType:"),
+        ns_string(val),
+        ns_string(kind),
+    ) != 0
+}
+
+/// Autoreleased `NSString` copy of the value.
+unsafe fn ns_string(val: &str) -> Id {
+    let msg: extern "C" fn(Id, Sel, *const c_void, c_ulong, c_ulong) -> Id =
+        mem::transmute(objc_msgSend as *const c_void);
+
+    let string = msg(
+        send(class("NSString"), "alloc"),
+        sel("initWithBytes:length:encoding:"),
+        val.as_ptr() as *const c_void,
+        val.len() as c_ulong,
+        NS_UTF8_STRING_ENCODING,
+    );
+
+    send(string, "autorelease")
+}
+
+unsafe fn send(receiver: Id, selector: &str) -> Id {
+    let msg: extern "C" fn(Id, Sel) -> Id = mem::transmute(objc_msgSend as *const c_void);
+    msg(receiver, sel(selector))
+}
+
+unsafe fn class(name: &str) -> Id {
+    let name = CString::new(name).expect("class name without NUL");
+    objc_getClass(name.as_ptr())
+}
+
+unsafe fn sel(name: &str) -> Sel {
+    let name = CString::new(name).expect("selector name without NUL");
+    sel_registerName(name.as_ptr())
+}
@@ -0,0 +1,217 @@
+use crate::Result;
+
+use xcb::{Atom, Connection, Window};
+
+use log::*;
+
+/// Klipper's convention, also honoured by GPaste, CopyQ, etc.
+const HINT_TARGET: &str = "x-kde-passwordManagerHint";
+
+/// Owner of CLIPBOARD selection serving a value until something else is copied.
+///
+/// Pasting programs ask the owner for the value, so it's gone once the owner exits.
+/// That's why it lives in a detached process, the same way `xclip` forks to serve it.
+/// `clipboard` crate offers a plain string only, so managers can't tell a secret from any other text.
+pub struct Selection {
+    conn: Connection,
+    window: Window,
+    atoms: Atoms,
+}
+
+#[derive(Clone, Copy)]
+struct Atoms {
+    clipboard: Atom,
+    targets: Atom,
+    hint: Atom,
+    utf8_string: Atom,
+    /// Targets served with the value as is
+    text: [Atom; 5],
+}
+
+impl Selection {
+    /// Takes over the clipboard from its current owner.
+    pub fn own() -> Result<Self> {
+        let (conn, screen) = Connection::connect(None)?;
+        let window = conn.generate_id();
+
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen as usize)
+            .ok_or("No X11 screen.")?;
+
+        xcb::create_window(
+            &conn,
+            xcb::COPY_FROM_PARENT as u8,
+            window,
+            root.root(),
+            0,
+            0,
+            1,
+            1,
+            0,
+            xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+            root.root_visual(),
+            &[],
+        );
+
+        let atoms = Atoms::new(&conn)?;
+
+        xcb::set_selection_owner(&conn, window, atoms.clipboard, xcb::CURRENT_TIME);
+
+        let current = xcb::get_selection_owner(&conn, atoms.clipboard)
+            .get_reply()?
+            .owner();
+
+        if current != window {
+            return Err("Can't own the clipboard.".into());
+        }
+
+        Ok(Selection {
+            conn,
+            window,
+            atoms,
+        })
+    }
+
+    /// Answers requests of pasting programs until another program owns the clipboard.
+    ///
+    /// Secrets come along with the hint asking managers to keep them out of history.
+    pub fn serve(&self, val: &str, secret: bool) {
+        let atoms = &self.atoms;
+
+        while let Some(event) = self.conn.wait_for_event() {
+            match event.response_type() & !0x80 {
+                xcb::SELECTION_REQUEST => {
+                    let req: &xcb::SelectionRequestEvent = unsafe { xcb::cast_event(&event) };
+
+                    // Obsolete clients leave the property to the owner
+                    let property = match req.property() {
+                        xcb::NONE => req.target(),
+                        property => property,
+                    };
+
+                    let replied = reply(&self.conn, req, property, atoms, val, secret);
+
+                    let notify = xcb::SelectionNotifyEvent::new(
+                        req.time(),
+                        req.requestor(),
+                        req.selection(),
+                        req.target(),
+                        if replied { property } else { xcb::NONE },
+                    );
+
+                    xcb::send_event(&self.conn, false, req.requestor(), 0, &notify);
+                    self.conn.flush();
+                }
+                xcb::SELECTION_CLEAR => {
+                    let owner = xcb::get_selection_owner(&self.conn, atoms.clipboard)
+                        .get_reply()
+                        .map(|r| r.owner());
+
+                    if owner.ok() != Some(self.window) {
+                        debug!("clipboard taken by another program");
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Atoms {
+    fn new(conn: &Connection) -> Result<Self> {
+        let atom = |name: &str| -> Result<Atom> {
+            Ok(xcb::intern_atom(conn, false, name).get_reply()?.atom())
+        };
+
+        let utf8_string = atom("UTF8_STRING")?;
+
+        Ok(Atoms {
+            clipboard: atom("CLIPBOARD")?,
+            targets: atom("TARGETS")?,
+            hint: atom(HINT_TARGET)?,
+            utf8_string,
+            text: [
+                utf8_string,
+                xcb::ATOM_STRING,
+                atom("TEXT")?,
+                atom("text/plain;charset=utf-8")?,
+                atom("text/plain")?,
+            ],
+        })
+    }
+}
+
+/// Leaves the clipboard without an owner, so the serving process exits.
+pub fn clear() -> Result<()> {
+    let (conn, _) = Connection::connect(None)?;
+    let atoms = Atoms::new(&conn)?;
+
+    xcb::set_selection_owner(&conn, xcb::NONE, atoms.clipboard, xcb::CURRENT_TIME);
+    conn.flush();
+
+    Ok(())
+}
+
+fn reply(
+    conn: &Connection,
+    req: &xcb::SelectionRequestEvent,
+    property: Atom,
+    atoms: &Atoms,
+    val: &str,
+    secret: bool,
+) -> bool {
+    let set = |kind, format, data: &[u8]| {
+        xcb::change_property(
+            conn,
+            xcb::PROP_MODE_REPLACE as u8,
+            req.requestor(),
+            property,
+            kind,
+            format,
+            data,
+        );
+    };
+
+    match req.target() {
+        target if target == atoms.targets => {
+            let hint = match secret {
+                true => [atoms.targets, atoms.hint].to_vec(),
+                false => [atoms.targets].to_vec(),
+            };
+            let targets = [hint.as_slice(), &atoms.text].concat();
+
+            xcb::change_property(
+                conn,
+                xcb::PROP_MODE_REPLACE as u8,
+                req.requestor(),
+                property,
+                xcb::ATOM_ATOM,
+                32,
+                &targets,
+            );
+        }
+        target if secret && target == atoms.hint => set(atoms.utf8_string, 8, b"secret"),
+        // Larger values need INCR transfers, which are left out. Secrets are never that long,
+        // but restored contents may be, they are then dropped rather than cut off.
+        _ if val.len() > max_property_len(conn) => return false,
+        target if atoms.text.contains(&target) => set(
+            match target == atoms.text[2] {
+                true => atoms.utf8_string, // TEXT lets the owner choose the type
+                false => target,
+            },
+            8,
+            val.as_bytes(),
+        ),
+        _ => return false,
+    }
+
+    true
+}
+
+/// Bytes that fit in a single ChangeProperty request, which is 4-byte units including the header.
+fn max_property_len(conn: &Connection) -> usize {
+    (conn.get_maximum_request_length() as usize * 4).saturating_sub(24)
+}
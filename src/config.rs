@@ -0,0 +1,121 @@
+use crate::Result;
+
+use serde_derive::Deserialize;
+
+use log::*;
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Named set of defaults from the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub database: Option<PathBuf>,
+    pub key_file: Option<PathBuf>,
+    pub use_keyring: bool,
+    /// `native` or `secret-service`
+    pub keyring_backend: Option<String>,
+    /// e.g. `8h`
+    pub keyring_ttl: Option<String>,
+    pub timeout: Option<u8>,
+    /// e.g. `osc52` on remote hosts
+    pub clipboard: Option<String>,
+    pub background: bool,
+    pub no_group: bool,
+    pub preview: bool,
+    pub full_screen: bool,
+    pub pinentry: bool,
+    pub pinentry_program: Option<PathBuf>,
+    pub password_file: Option<PathBuf>,
+    /// Variable holding the master password
+    pub password_env: Option<String>,
+    /// e.g. `1h`
+    pub agent_timeout: Option<String>,
+    /// Other profiles whose databases are searched together
+    pub profiles: Vec<String>,
+    #[serde(skip)]
+    pub members: Vec<(String, Profile)>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// Profile to use when none is given on the command line
+    default: Option<String>,
+    profiles: HashMap<String, Profile>,
+}
+
+fn config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join(crate::BIN_NAME).join("config.toml"))
+}
+
+/// Returns the profile by name or the default one.
+///
+/// Missing config file is fine unless a profile was asked explicitly.
+pub fn load_profile(name: Option<&str>) -> Result<Option<Profile>> {
+    let path = match (config_path(), name) {
+        (Some(path), _) => path,
+        (None, None) => return Ok(None),
+        (None, Some(_)) => {
+            return Err("Can't locate config file, neither XDG_CONFIG_HOME nor HOME set.".into())
+        }
+    };
+
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && name.is_none() => return Ok(None),
+        Err(e) => return Err(format!("Can't read `{}`. {}", path.display(), e).into()),
+    };
+
+    let mut config: Config = toml::from_str(&content)
+        .map_err(|e| format!("Invalid config file `{}`. {}", path.display(), e))?;
+
+    let name = match name.or(config.default.as_deref()) {
+        Some(name) => name.to_owned(),
+        None => return Ok(None),
+    };
+
+    debug!("using profile `{}` from {}", name, path.display());
+
+    let mut profile = config
+        .profiles
+        .remove(&name)
+        .ok_or_else(|| format!("No profile `{}` in `{}`.", name, path.display()))?;
+
+    for member in &profile.profiles {
+        let mut m = config
+            .profiles
+            .remove(member)
+            .ok_or_else(|| format!("No profile `{}` in `{}`.", member, path.display()))?;
+
+        if !m.profiles.is_empty() {
+            return Err(format!("Profile `{}` can't list other profiles.", member).into());
+        }
+
+        m.database = m.database.map(expand_tilde);
+        m.key_file = m.key_file.map(expand_tilde);
+        profile.members.push((member.to_owned(), m));
+    }
+
+    profile.database = profile.database.map(expand_tilde);
+    profile.key_file = profile.key_file.map(expand_tilde);
+    profile.pinentry_program = profile.pinentry_program.map(expand_tilde);
+    profile.password_file = profile.password_file.map(expand_tilde);
+
+    Ok(Some(profile))
+}
+
+fn expand_tilde(path: PathBuf) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => path,
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{resolve::Resolver, utils::*, Args, Result};
+
+use url::Url;
+
+use log::*;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+/// Implements git credential helper protocol, see `man gitcredentials`.
+///
+/// Only `get` is answered since databases are read-only;
+/// `store` and `erase` are accepted and ignored.
+pub(super) fn run(args: Args) -> Result<()> {
+    let request = read_request()?;
+    debug!("git credential request: {:?}", request.keys());
+
+    match args.arg_entry.as_deref() {
+        Some("get") => {}
+        Some("store") | Some("erase") => return Ok(()),
+        Some(action) => return Err(format!("Unknown git credential action `{}`.", action).into()),
+        None => return Err("No git credential action given.".into()),
+    }
+
+    let (protocol, host) = match (request.get("protocol"), request.get("host")) {
+        (Some(protocol), Some(host)) => (protocol.as_str(), host.as_str()),
+        // Nothing to look for, let git ask somebody else
+        _ => return Ok(()),
+    };
+
+    let path = request
+        .get("path")
+        .map(|p| p.trim_matches('/'))
+        .unwrap_or("");
+    let username = request.get("username");
+
+    let dbs = open_databases(&args.databases).map_err(|e| {
+        // Stdin belongs to git, so the password comes from the keyring or pinentry
+        format!(
+            "{}\nGit owns STDIN, store the password with `kp -p ls` first or use `--pinentry`.",
+            e
+        )
+    })?;
+    let entries = Entries::new(&dbs, &args.databases);
+
+    let mut best = None;
+
+    for (db, entry) in entries.iter() {
+        let resolver = Resolver::new(entries.of(db));
+
+        let url = match resolver.field(entry, Some("url")) {
+            Ok(url) if !url.is_empty() => url,
+            _ => continue,
+        };
+
+        let entry_path = match matching_path(&url, protocol, host, path) {
+            Some(entry_path) => entry_path,
+            None => continue,
+        };
+
+        let entry_user = resolver.field(entry, Some("username")).unwrap_or_default();
+
+        if username.is_some_and(|u| *u != entry_user) {
+            continue;
+        }
+
+        // More specific path wins, unless git sends no path (`credential.useHttpPath`)
+        let rank = match path.is_empty() {
+            true => -(entry_path.len() as isize),
+            false => entry_path.len() as isize,
+        };
+
+        if best.as_ref().is_none_or(|&(r, _, _)| rank > r) {
+            best = Some((rank, entry_user, resolver.field(entry, None)?));
+        }
+    }
+
+    if let Some((_, username, password)) = best {
+        if username.contains('\n') || password.contains('\n') {
+            return Err("Credentials with line breaks can't be passed to git.".into());
+        }
+
+        wout!("username={}", username);
+        wout!("password={}", password);
+    }
+
+    Ok(())
+}
+
+/// Reads `key=value` lines until an empty line or EOF.
+fn read_request() -> Result<HashMap<String, String>> {
+    let mut request = HashMap::new();
+
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((key, val)) = line.split_once('=') {
+            request.insert(key.to_owned(), val.to_owned());
+        }
+    }
+
+    Ok(request)
+}
+
+/// Returns entry's URL path if it covers the requested protocol, host and path.
+///
+/// No requested path matches any path, e.g. of a login page.
+fn matching_path(url: &str, protocol: &str, host: &str, path: &str) -> Option<String> {
+    // URLs are often stored without scheme, e.g. `github.com`
+    let url = Url::parse(url)
+        .ok()
+        .filter(|u| u.has_host())
+        .or_else(|| Url::parse(&format!("{}://{}", protocol, url)).ok())?;
+
+    let entry_host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str()?, port),
+        None => url.host_str()?.to_owned(),
+    };
+
+    if url.scheme() != protocol || !entry_host.eq_ignore_ascii_case(host) {
+        return None;
+    }
+
+    let entry_path = url.path().trim_matches('/');
+
+    let covered = path.is_empty()
+        || entry_path.is_empty()
+        || path == entry_path
+        || path
+            .strip_prefix(entry_path)
+            .is_some_and(|rest| rest.starts_with('/'));
+
+    if !covered {
+        return None;
+    }
+
+    Some(entry_path.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_path() {
+        assert_eq!(
+            matching_path("https://github.com/login", "https", "github.com", ""),
+            Some("login".to_owned())
+        );
+        assert_eq!(
+            matching_path("https://github.com", "https", "github.com", ""),
+            Some("".to_owned())
+        );
+        assert_eq!(
+            matching_path("https://github.com/login", "ssh", "github.com", ""),
+            None
+        );
+        assert_eq!(
+            matching_path("https://gitlab.com/login", "https", "github.com", ""),
+            None
+        );
+    }
+
+    #[test]
+    fn prefix_path() {
+        let path = "team/repo.git";
+
+        assert_eq!(
+            matching_path("https://example.com/team", "https", "example.com", path),
+            Some("team".to_owned())
+        );
+        assert_eq!(
+            matching_path(
+                "https://example.com/team/repo.git/",
+                "https",
+                "example.com",
+                path
+            ),
+            Some("team/repo.git".to_owned())
+        );
+        assert_eq!(
+            matching_path("https://example.com/tea", "https", "example.com", path),
+            None
+        );
+        assert_eq!(
+            matching_path("https://example.com/other", "https", "example.com", path),
+            None
+        );
+    }
+
+    #[test]
+    fn port() {
+        assert_eq!(
+            matching_path("https://example.com:8443/", "https", "example.com:8443", ""),
+            Some("".to_owned())
+        );
+        assert_eq!(
+            matching_path("https://example.com/", "https", "example.com:8443", ""),
+            None
+        );
+        assert_eq!(
+            matching_path("https://example.com:8443/", "https", "example.com", ""),
+            None
+        );
+    }
+
+    #[test]
+    fn no_scheme() {
+        assert_eq!(
+            matching_path("github.com", "https", "github.com", "user/repo"),
+            Some("".to_owned())
+        );
+        assert_eq!(
+            matching_path(
+                "Example.com:8443/git",
+                "https",
+                "example.com:8443",
+                "git/repo"
+            ),
+            Some("git".to_owned())
+        );
+    }
+}
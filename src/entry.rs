@@ -0,0 +1,141 @@
+use crate::pwd::Pwd;
+use crate::Result;
+
+use kdbx4::Database;
+use once_cell::unsync::OnceCell;
+use serde_derive::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// Fields shown apart from the rest, see `Display`.
+const STANDARD: [&str; 3] = ["UserName", "URL", "Notes"];
+
+/// Fields left out of the rest, the password being `(protected)` anyway.
+const HIDDEN: [&str; 2] = ["Title", "Password"];
+
+/// Entry copied out of the database, so it can outlive it, e.g. in the agent.
+///
+/// Field values are kept as kdbx4 gives them, i.e. XML escaped. The password is
+/// decrypted only when asked for, see [`Entry::copy_password`] for the agent.
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    uuid: String,
+    title: String,
+    group: String,
+    props: BTreeMap<String, String>,
+    #[serde(with = "copied")]
+    password: OnceCell<Pwd>,
+    /// Decrypts the password, not set for entries from the agent
+    #[serde(skip)]
+    database: Option<Rc<Database>>,
+}
+
+impl Entry {
+    pub fn new<'a>(entry: &'a kdbx4::Entry<'a>, database: &Rc<Database>) -> Self {
+        // kdbx4 has no way to list fields but printing them all, so every
+        // `  <name>: ` prefix of the printout is a candidate checked with `prop`
+        let printout = entry.to_string();
+
+        let props = printout
+            .lines()
+            .filter_map(|line| line.strip_prefix("  "))
+            .flat_map(|line| line.match_indices(": ").map(move |(end, _)| &line[..end]))
+            .chain(HIDDEN.into_iter().chain(STANDARD))
+            .filter_map(|name| Some((name.to_owned(), entry.prop(name)?.to_owned())))
+            .collect();
+
+        Entry {
+            uuid: entry.uuid().to_owned(),
+            title: entry.title().to_owned(),
+            group: entry.group(),
+            props,
+            password: OnceCell::new(),
+            database: Some(Rc::clone(database)),
+        }
+    }
+
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    pub fn prop(&self, name: &str) -> Option<&str> {
+        self.props.get(name).map(String::as_str)
+    }
+
+    /// Fields other than the standard ones, e.g. `otp` of KeePassXC.
+    pub fn custom_props(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.props
+            .iter()
+            .map(|(name, val)| (name.as_str(), val.as_str()))
+            .filter(|(name, _)| !HIDDEN.contains(name) && !STANDARD.contains(name))
+    }
+
+    pub fn password(&self) -> Result<&Pwd> {
+        self.password.get_or_try_init(|| {
+            let entries = self.database.as_ref().map(|db| db.entries());
+
+            // kdbx4 entries borrow the database, so it's looked up again
+            entries
+                .iter()
+                .flatten()
+                .find(|e| e.uuid() == self.uuid)
+                .and_then(|e| e.password().ok())
+                .map(Pwd::from)
+                .ok_or_else(|| format!("Can't decrypt the password of `{}`.", self.title).into())
+        })
+    }
+
+    /// Decrypts the password ahead, so the entry can be passed on without the database.
+    pub fn copy_password(&self) {
+        let _ = self.password();
+    }
+}
+
+/// Password as it goes from the agent, `None` if it couldn't be decrypted.
+mod copied {
+    use super::*;
+
+    pub fn serialize<S: serde::Serializer>(
+        pwd: &OnceCell<Pwd>,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&pwd.get(), serializer)
+    }
+
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<OnceCell<Pwd>, D::Error> {
+        let pwd: Option<Pwd> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(pwd.map(OnceCell::from).unwrap_or_default())
+    }
+}
+
+/// Same as kdbx4 prints entries.
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "  Title: {}", self.title)?;
+        writeln!(f, "  Group: //{}", self.group)?;
+
+        for name in STANDARD {
+            if let Some(val) = self.prop(name).filter(|v| !v.is_empty()) {
+                writeln!(f, "  {}: {}", name, val)?;
+            }
+        }
+
+        for (name, val) in self.custom_props() {
+            writeln!(f, "  {}: {}", name, val)?;
+        }
+
+        Ok(())
+    }
+}
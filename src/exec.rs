@@ -0,0 +1,90 @@
+use crate::{entry::Entry, pwd, resolve::Resolver, utils::*, Args, Result};
+
+use log::*;
+
+use std::os::unix::process::CommandExt;
+use std::process;
+
+pub(super) fn run(args: Args) -> Result<()> {
+    let (mappings, program) = parse_args(&args.arg_args)?;
+
+    let dbs = open_databases(&args.databases)?;
+    let entries = Entries::new(&dbs, &args.databases);
+
+    let mut cmd = process::Command::new(&program[0]);
+    cmd.args(&program[1..]);
+
+    for (var, query) in mappings {
+        let (db, entry) = find_single(&entries, query, args.flag_url)?;
+        let val = Resolver::new(entries.of(db)).field(entry, args.flag_field.as_deref())?;
+
+        debug!("{} is set from `{}`", var, entry.title());
+        cmd.env(var, val);
+    }
+
+    // Dumpable flag is reset by exec itself
+    pwd::restore_core_limit();
+
+    // Returns only if the program can't be started
+    let err = cmd.exec();
+
+    Err(format!("Can't run `{}`. {}", program[0], err).into())
+}
+
+/// Environment variable name and entry's query
+type Mapping<'a> = (&'a str, &'a str);
+
+/// Splits `-e VAR=entry... [--] program [args...]`.
+fn parse_args(args: &[String]) -> Result<(Vec<Mapping<'_>>, &[String])> {
+    let mut mappings = Vec::new();
+    let mut idx = 0;
+
+    while idx < args.len() {
+        let mapping = match args[idx].as_str() {
+            "-e" | "--env" => {
+                idx += 1;
+                args.get(idx).map(String::as_str)
+            }
+            arg if arg.starts_with("--env=") => Some(&arg["--env=".len()..]),
+            arg if arg.starts_with("-e") => Some(&arg["-e".len()..]),
+            _ => break,
+        };
+
+        let (var, query) = mapping
+            .and_then(|m| m.split_once('='))
+            .filter(|(var, query)| !var.is_empty() && !query.is_empty())
+            .ok_or("Expected `-e VAR=entry` mapping.")?;
+
+        mappings.push((var, query));
+        idx += 1;
+    }
+
+    if idx == args.len() {
+        return Err("No program to run. Use `--help` to get more info.".into());
+    }
+
+    Ok((mappings, &args[idx..]))
+}
+
+/// There is no way to ask which entry is meant, so an exact title wins over other matches.
+fn find_single<'a>(
+    entries: &'a Entries<'a>,
+    query: &str,
+    by_url: bool,
+) -> Result<(usize, &'a Entry)> {
+    let found = entries.search_unattended(query, by_url)?;
+
+    if let [single] = found.as_slice() {
+        return Ok(*single);
+    }
+
+    match found
+        .iter()
+        .filter(|(_, e)| e.title().eq_ignore_ascii_case(query))
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [single] => Ok(**single),
+        _ => Err(format!("No single match for {}.", query).into()),
+    }
+}
@@ -0,0 +1,171 @@
+use crate::resolve::{Resolver, MASK};
+use crate::{entry::Entry, pwd::Pwd, utils::Entries, Result};
+
+use serde_derive::Serialize;
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+    Yaml,
+    Tsv,
+}
+
+impl Format {
+    pub fn parse(format: Option<&str>) -> Result<Self> {
+        Ok(match &*format.unwrap_or("text").to_lowercase() {
+            "text" => Format::Text,
+            "json" => Format::Json,
+            "yaml" | "yml" => Format::Yaml,
+            "tsv" => Format::Tsv,
+            f => {
+                return Err(format!(
+                    "Unknown format `{}`. Use `text`, `json`, `yaml` or `tsv`.",
+                    f
+                )
+                .into())
+            }
+        })
+    }
+}
+
+/// Entry's fields flattened for serialisation.
+///
+/// Password is masked unless explicitly revealed. Protected custom fields are
+/// always masked, as kdbx4 decrypts nothing but the password.
+#[derive(Serialize)]
+pub struct Record<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    database: Option<&'a str>,
+    uuid: &'a str,
+    title: &'a str,
+    group: String,
+    username: String,
+    url: String,
+    notes: String,
+    password: Pwd,
+    /// Custom string fields, left out of `tsv`
+    fields: BTreeMap<&'a str, String>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(
+        entries: &'a Entries<'a>,
+        db: usize,
+        entry: &'a Entry,
+        reveal: bool,
+    ) -> Result<Self> {
+        let resolver = Resolver::for_display(entries.of(db), reveal);
+        let field = |f| resolver.field(entry, Some(f)).unwrap_or_default();
+
+        Ok(Record {
+            database: entries.label(db),
+            uuid: entry.uuid(),
+            title: entry.title(),
+            group: entry.group().to_owned(),
+            username: field("username"),
+            url: field("url"),
+            notes: field("notes"),
+            // Masked by the resolver unless revealed
+            password: Pwd::from(resolver.field(entry, None)?),
+            fields: entry
+                .custom_props()
+                .map(|(name, val)| match val {
+                    "(protected)" => (name, MASK.to_owned()),
+                    _ => (name, field(name)),
+                })
+                .collect(),
+        })
+    }
+
+    fn tsv_header() -> &'static str {
+        "uuid\ttitle\tgroup\tusername\turl\tnotes\tpassword\tdatabase"
+    }
+
+    fn tsv(&self) -> String {
+        [
+            self.uuid,
+            self.title,
+            &self.group,
+            &self.username,
+            &self.url,
+            &self.notes,
+            &self.password,
+            self.database.unwrap_or_default(),
+        ]
+        .iter()
+        .map(|f| escape_tsv(f))
+        .collect::<Vec<_>>()
+        .join("\t")
+    }
+}
+
+pub fn print_entry<'a>(
+    entries: &'a Entries<'a>,
+    db: usize,
+    entry: &'a Entry,
+    format: &Format,
+    reveal: bool,
+) -> Result<()> {
+    let record = Record::new(entries, db, entry, reveal)?;
+
+    match format {
+        Format::Text => {
+            wout!("-----");
+            let resolver = Resolver::for_display(entries.of(db), reveal);
+            if let Some(label) = entries.label(db) {
+                wout!("  Database: {}", label);
+            }
+            put!("{}", resolver.text(entry, &entry.to_string())?);
+            wout!("-----");
+        }
+        Format::Json => wout!("{}", serde_json::to_string_pretty(&record)?),
+        Format::Yaml => {
+            put!("{}", serde_yaml::to_string(&record)?);
+        }
+        Format::Tsv => {
+            wout!("{}", Record::tsv_header());
+            wout!("{}", record.tsv());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn print_entries<'a>(
+    entries: &'a Entries<'a>,
+    selected: &[(usize, &'a Entry)],
+    format: &Format,
+    reveal: bool,
+) -> Result<()> {
+    let records = selected
+        .iter()
+        .map(|&(db, e)| Record::new(entries, db, e, reveal))
+        .collect::<Result<Vec<_>>>()?;
+
+    match format {
+        Format::Text => unreachable!("text output is handled by the command itself"),
+        Format::Json => wout!("{}", serde_json::to_string_pretty(&records)?),
+        Format::Yaml => {
+            put!("{}", serde_yaml::to_string(&records)?);
+        }
+        Format::Tsv => {
+            wout!("{}", Record::tsv_header());
+            for record in &records {
+                wout!("{}", record.tsv());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn escape_tsv(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
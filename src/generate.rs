@@ -0,0 +1,152 @@
+use crate::{clip::copy, utils::*, Args, Result};
+
+use log::*;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::result;
+
+pub(super) const DEFAULT_LENGTH: usize = 20;
+pub(super) const DEFAULT_WORDLIST: &str = "/usr/share/dict/words";
+const WORD_SEPARATOR: &str = "-";
+
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const DIGITS: &str = "0123456789";
+const SYMBOLS: &str = "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~";
+const LOOKALIKES: &str = "Il1|O0o`'\"";
+
+pub(super) fn run(args: Args) -> Result<()> {
+    let (pwd, entropy) = match args.flag_words {
+        Some(words) => passphrase(
+            words,
+            args.flag_wordlist
+                .as_deref()
+                .unwrap_or_else(|| Path::new(DEFAULT_WORDLIST)),
+        )?,
+        None => password(
+            args.flag_length.unwrap_or(DEFAULT_LENGTH),
+            args.flag_charset.as_deref(),
+            args.flag_no_lookalike,
+        )?,
+    };
+
+    // Stderr keeps the entropy out of pipes and command substitutions
+    werr!("Estimated entropy: {:.0} bits", entropy);
+
+    if !is_tty(io::stdout()) {
+        put!("{}", pwd);
+        return Ok(());
+    }
+
+    copy(&pwd, args.flag_timeout.map(u64::from), &args)
+        .map_err(|_| "Clipboard unavailable. Try use STDOUT, i.e. `kp generate | cat`.".into())
+}
+
+/// Returns random characters from the selected classes and its entropy in bits.
+///
+/// Every class is present at least once if the length allows it.
+fn password(length: usize, charset: Option<&str>, no_lookalike: bool) -> Result<(String, f64)> {
+    let mut classes = charset
+        .unwrap_or("lower,upper,digits,symbols")
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|c| match &*c.to_lowercase() {
+            "lower" => Ok(LOWER),
+            "upper" => Ok(UPPER),
+            "digits" => Ok(DIGITS),
+            "symbols" => Ok(SYMBOLS),
+            _ => Err(format!(
+                "Unknown character class `{}`. Use `lower`, `upper`, `digits` or `symbols`.",
+                c
+            )),
+        })
+        .map(|class| {
+            class.map(|chars| {
+                chars
+                    .chars()
+                    .filter(|&c| !no_lookalike || !LOOKALIKES.contains(c))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect::<result::Result<Vec<_>, String>>()?;
+
+    // Repeated class would weigh its characters twice and overstate the entropy
+    classes.sort_unstable();
+    classes.dedup();
+
+    let alphabet = classes.concat();
+
+    if alphabet.is_empty() || length == 0 {
+        return Err("Nothing to generate a password from.".into());
+    }
+
+    let pwd = loop {
+        let pwd = (0..length)
+            .map(|_| random_index(alphabet.len()).map(|idx| alphabet[idx]))
+            .collect::<Result<Vec<_>>>()?;
+
+        if length < classes.len() || classes.iter().all(|c| c.iter().any(|c| pwd.contains(c))) {
+            break pwd.into_iter().collect();
+        }
+
+        debug!("not all character classes are used, trying again");
+    };
+
+    Ok((pwd, length as f64 * (alphabet.len() as f64).log2()))
+}
+
+/// Returns random words from the list and its entropy in bits.
+///
+/// Both EFF diceware (`11111<TAB>abacus`) and plain (a word per line) lists work.
+fn passphrase(words: usize, wordlist: &Path) -> Result<(String, f64)> {
+    let content = fs::read_to_string(wordlist)
+        .map_err(|e| format!("Can't read wordlist `{}`. {}", wordlist.display(), e))?;
+
+    let mut list = content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        // Skip proper nouns and possessives of system dictionaries
+        .filter(|w| w.chars().all(|c| c.is_ascii_lowercase() || c == '-'))
+        .collect::<Vec<_>>();
+
+    list.sort_unstable();
+    list.dedup();
+
+    debug!("wordlist of {} words", list.len());
+
+    if list.len() < 2 || words == 0 {
+        return Err(format!(
+            "Nothing to generate a passphrase from `{}`.",
+            wordlist.display()
+        )
+        .into());
+    }
+
+    let pwd = (0..words)
+        .map(|_| random_index(list.len()).map(|idx| list[idx]))
+        .collect::<Result<Vec<_>>>()?
+        .join(WORD_SEPARATOR);
+
+    Ok((pwd, words as f64 * (list.len() as f64).log2()))
+}
+
+/// Uniformly distributed index from OS random source.
+fn random_index(len: usize) -> Result<usize> {
+    let len = len as u64;
+
+    // Rejection sampling avoids modulo bias
+    let zone = u64::MAX - u64::MAX % len;
+
+    loop {
+        let mut buf = [0u8; 8];
+        getrandom::getrandom(&mut buf).map_err(|e| format!("No random numbers: {}", e))?;
+
+        let num = u64::from_ne_bytes(buf);
+        if num < zone {
+            return Ok((num % len) as usize);
+        }
+    }
+}
@@ -2,14 +2,69 @@ use crate::pwd::Pwd;
 
 use log::*;
 
+use sha2::{Digest, Sha256};
+
 use std::fmt;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
 use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(target_os = "linux")]
+mod freedesktop;
+
+/// Storage of master passwords.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Keychain on macOS, kernel's session keyring on Linux
+    #[default]
+    Native,
+    /// freedesktop.org Secret Service on Linux
+    SecretService,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "native" => Ok(Backend::Native),
+            "secret-service" => Ok(Backend::SecretService),
+            _ => Err(format!(
+                "Unknown keyring backend `{}`. Use `native` or `secret-service`.",
+                name
+            )),
+        }
+    }
+}
 
 impl Keyring {
-    pub fn from_db_path(file: impl AsRef<Path>) -> Option<Self> {
-        let (service, username) = create_from(&file.as_ref().to_string_lossy());
+    /// Slot of the database file, same for every path (relative, symlink, etc.) leading to it.
+    pub fn from_db_path(file: impl AsRef<Path>, backend: Backend) -> Option<Self> {
+        let file = file.as_ref();
+        let path = fs::canonicalize(file).unwrap_or_else(|_| file.to_owned());
+
+        let account = Sha256::digest(path.as_os_str().as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Keyring::init(service_name(), account, &path.to_string_lossy(), backend)
+    }
+
+    /// Slot of earlier versions keyed by the path exactly as typed.
+    ///
+    /// Looked up only to move the password to [`Keyring::from_db_path`] slot.
+    pub fn legacy(file: impl AsRef<Path>, backend: Backend) -> Option<Self> {
+        let path = file.as_ref().to_string_lossy();
+        let (service, username) = create_from(&path);
 
-        Keyring::new(service, username)
+        Keyring::init(service, username, &path, backend)
+    }
+
+    fn init(service: String, account: String, path: &str, backend: Backend) -> Option<Self> {
+        Keyring::new(service, account, path, backend)
             .map(Some)
             .unwrap_or_else(|e| {
                 warn!("can't init keyring ({})", e);
@@ -18,11 +73,30 @@ impl Keyring {
     }
 }
 
+/// Unix time when a password stored now should be gone.
+fn expires_at(ttl: Duration) -> u64 {
+    unix_now().saturating_add(ttl.as_secs())
+}
+
+fn is_expired(at: u64) -> bool {
+    unix_now() >= at
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn service_name() -> String {
+    format!("{}.keepass.cli.tool", crate::BIN_NAME)
+}
+
 fn create_from(filename: &str) -> (String, String) {
-    let service = format!("{}.keepass.cli.tool", crate::BIN_NAME);
     let username = format!("{}", hash(filename));
 
-    (service, username)
+    (service_name(), username)
 }
 
 fn hash(data: &str) -> u64 {
@@ -53,7 +127,11 @@ impl fmt::Display for Keyring {
 
 #[cfg(target_os = "macos")]
 impl Keyring {
-    fn new(keyname: String, account: String) -> Result<Self, String> {
+    fn new(keyname: String, account: String, _: &str, backend: Backend) -> Result<Self, String> {
+        if backend == Backend::SecretService {
+            return Err("Secret Service is not available on macOS.".to_owned());
+        }
+
         SecKeychain::default()
             .map(|keychain| Keyring {
                 keyname,
@@ -63,7 +141,22 @@ impl Keyring {
             .map_err(|e| format!("{}", e))
     }
 
+    /// Keychain doesn't expire items, so the deadline is kept in a sibling item.
+    fn expiry_name(&self) -> String {
+        format!("{}.expires", self.keyname)
+    }
+
     pub fn get_password(&self) -> Result<Pwd, String> {
+        if let Ok((at, _)) = self
+            .keychain
+            .find_generic_password(&self.expiry_name(), &self.account)
+        {
+            if is_expired(String::from_utf8_lossy(&at).parse().unwrap_or(0)) {
+                let _ = self.delete_password();
+                return Err("Password expired.".to_owned());
+            }
+        }
+
         self.keychain
             .find_generic_password(&self.keyname, &self.account)
             .map(|(pwd, _)| unsafe { String::from_utf8_unchecked(pwd.to_owned()) })
@@ -71,22 +164,52 @@ impl Keyring {
             .map_err(|e| format!("{}", e))
     }
 
-    pub fn set_password(&self, password: &str) -> Result<(), String> {
+    pub fn set_password(&self, password: &str, ttl: Option<Duration>) -> Result<(), String> {
+        match ttl {
+            Some(ttl) => self
+                .keychain
+                .set_generic_password(
+                    &self.expiry_name(),
+                    &self.account,
+                    expires_at(ttl).to_string().as_bytes(),
+                )
+                .map_err(|e| format!("{}", e))?,
+            None => self.delete_expiry(),
+        }
+
         self.keychain
             .set_generic_password(&self.keyname, &self.account, password.as_bytes())
             .map_err(|e| format!("{}", e))
     }
 
+    /// Whether the password is kept for a limited time, i.e. stored with a TTL.
+    pub fn has_expiry(&self) -> bool {
+        self.keychain
+            .find_generic_password(&self.expiry_name(), &self.account)
+            .is_ok()
+    }
+
     pub fn delete_password(&self) -> Result<(), String> {
+        self.delete_expiry();
+
         self.keychain
             .find_generic_password(&self.keyname, &self.account)
             .map(|(_, key)| key.delete())
             .map_err(|e| format!("{}", e))
     }
+
+    fn delete_expiry(&self) {
+        if let Ok((_, key)) = self
+            .keychain
+            .find_generic_password(&self.expiry_name(), &self.account)
+        {
+            key.delete();
+        }
+    }
 }
 
 #[cfg(target_os = "linux")]
-use libc::{c_char, c_int, syscall, SYS_add_key, SYS_keyctl, SYS_request_key};
+use libc::{c_char, c_int, c_long, c_uint, syscall, SYS_add_key, SYS_keyctl, SYS_request_key};
 
 #[cfg(target_os = "linux")]
 use std::{ffi::CString, io, ptr};
@@ -97,34 +220,35 @@ use log::*;
 #[cfg(target_os = "linux")]
 pub struct Keyring {
     desc: CString,
+    secret: Option<freedesktop::Secret>,
 }
 
 #[cfg(target_os = "linux")]
 impl Keyring {
-    fn new(keyname: String, account: String) -> Result<Self, String> {
-        let desc = [keyname, account].join(":").into_bytes();
+    fn new(keyname: String, account: String, path: &str, backend: Backend) -> Result<Self, String> {
+        let desc = [keyname.as_str(), account.as_str()].join(":").into_bytes();
         let desc = unsafe { CString::from_vec_unchecked(desc) };
 
-        Ok(Keyring { desc })
+        let secret = match backend {
+            Backend::Native => None,
+            Backend::SecretService => {
+                Some(freedesktop::Secret::new(keyname, account, path.to_owned()))
+            }
+        };
+
+        Ok(Keyring { desc, secret })
     }
 
     pub fn get_password(&self) -> Result<Pwd, String> {
         const KEYCTL_READ: c_int = 11;
 
-        info!("keyctl key decription: {:?}", self.desc);
+        if let Some(secret) = &self.secret {
+            return secret.get_password();
+        }
 
-        let pwd = unsafe {
-            let key_id = match syscall(
-                SYS_request_key,
-                b"user\0", // type for user-defined keyrings
-                self.desc.as_ptr(),
-                ptr::null::<c_char>(),
-                0,
-            ) {
-                -1 => return Err(format!("{}", io::Error::last_os_error())),
-                id => id,
-            };
+        let key_id = self.key_id()?;
 
+        let pwd = unsafe {
             let data_len = match syscall(SYS_keyctl, KEYCTL_READ, key_id, ptr::null::<c_char>(), 0)
             {
                 -1 => return Err(format!("{}", io::Error::last_os_error())),
@@ -143,22 +267,35 @@ impl Keyring {
         Ok(pwd.into())
     }
 
-    pub fn set_password(&self, password: &str) -> Result<(), String> {
+    pub fn set_password(&self, password: &str, ttl: Option<Duration>) -> Result<(), String> {
         const KEY_SPEC_SESSION_KEYRING: c_int = -3;
+        const KEYCTL_SET_TIMEOUT: c_int = 15;
+
+        if let Some(secret) = &self.secret {
+            return secret.set_password(password, ttl);
+        }
 
         info!("keyctl key decription: {:?}", self.desc);
 
         unsafe {
-            if -1
-                == syscall(
-                    SYS_add_key,
-                    b"user\0",
-                    self.desc.as_ptr(),
-                    password.as_ptr(),
-                    password.len(),
-                    KEY_SPEC_SESSION_KEYRING,
-                )
-            {
+            let key_id = match syscall(
+                SYS_add_key,
+                b"user\0",
+                self.desc.as_ptr(),
+                password.as_ptr(),
+                password.len(),
+                KEY_SPEC_SESSION_KEYRING,
+            ) {
+                -1 => return Err(format!("{}", io::Error::last_os_error())),
+                id => id,
+            };
+
+            // Zero clears a timeout left by the previous `add_key` of the same key
+            let secs = ttl.map_or(0, |ttl| {
+                ttl.as_secs().clamp(1, c_uint::MAX as u64) as c_uint
+            });
+
+            if -1 == syscall(SYS_keyctl, KEYCTL_SET_TIMEOUT, key_id, secs) {
                 return Err(format!("{}", io::Error::last_os_error()));
             }
         }
@@ -169,32 +306,67 @@ impl Keyring {
     pub fn delete_password(&self) -> Result<(), String> {
         const KEYCTL_INVALIDATE: c_int = 21;
 
+        if let Some(secret) = &self.secret {
+            return secret.delete_password();
+        }
+
+        let key_id = self.key_id()?;
+
+        if -1 == unsafe { syscall(SYS_keyctl, KEYCTL_INVALIDATE, key_id) } {
+            return Err(format!("{}", io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the password is kept for a limited time, i.e. stored with a TTL.
+    pub fn has_expiry(&self) -> bool {
+        if let Some(secret) = &self.secret {
+            return secret.has_expiry();
+        }
+
+        let key_id = match self.key_id() {
+            Ok(id) => format!("{:08x}", id),
+            Err(_) => return false,
+        };
+
+        // keyctl doesn't tell the timeout, `/proc/keys` has it as the 4th column, `perm` for none
+        fs::read_to_string("/proc/keys")
+            .map(|keys| {
+                keys.lines()
+                    .map(|line| line.split_whitespace().take(4).collect::<Vec<_>>())
+                    .any(|cols| {
+                        cols.first() == Some(&key_id.as_str()) && cols.get(3) != Some(&"perm")
+                    })
+            })
+            // Unknown is taken for set, otherwise every use would push the deadline
+            .unwrap_or(true)
+    }
+
+    fn key_id(&self) -> Result<c_long, String> {
         info!("keyctl key decription: {:?}", self.desc);
 
-        unsafe {
-            let key_id = match syscall(
+        match unsafe {
+            syscall(
                 SYS_request_key,
                 b"user\0", // type for user-defined keyrings
                 self.desc.as_ptr(),
                 ptr::null::<c_char>(),
                 0,
-            ) {
-                -1 => return Err(format!("{}", io::Error::last_os_error())),
-                id => id,
-            };
-
-            if -1 == syscall(SYS_keyctl, KEYCTL_INVALIDATE, key_id) {
-                return Err(format!("{}", io::Error::last_os_error()));
-            }
+            )
+        } {
+            -1 => Err(format!("{}", io::Error::last_os_error())),
+            id => Ok(id),
         }
-
-        Ok(())
     }
 }
 
 #[cfg(target_os = "linux")]
 impl fmt::Display for Keyring {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "description: {})", self.desc.to_string_lossy())
+        match &self.secret {
+            Some(secret) => write!(f, "secret service: {}", secret),
+            None => write!(f, "description: {})", self.desc.to_string_lossy()),
+        }
     }
 }
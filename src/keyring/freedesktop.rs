@@ -0,0 +1,209 @@
+use super::{expires_at, is_expired};
+use crate::pwd::Pwd;
+
+use secret_service::blocking::{Item, SecretService};
+use secret_service::EncryptionType;
+
+use log::*;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Item of freedesktop.org Secret Service (GNOME Keyring, KWallet, KeePassXC, etc.).
+///
+/// Unlike the session keyring it lives as long as the desktop's keyring does.
+pub struct Secret {
+    service: String,
+    account: String,
+    database: String,
+}
+
+impl Secret {
+    pub fn new(service: String, account: String, database: String) -> Self {
+        Secret {
+            service,
+            account,
+            database,
+        }
+    }
+
+    /// Database path is informational only, `service` and `account` are enough to find an item.
+    fn attributes(&self) -> HashMap<&str, &str> {
+        HashMap::from([
+            ("service", self.service.as_str()),
+            ("account", self.account.as_str()),
+        ])
+    }
+
+    pub fn get_password(&self) -> Result<Pwd, String> {
+        let ss = connect()?;
+
+        with_item(&ss, self.attributes(), |item| {
+            let attributes = item.get_attributes().map_err(|e| e.to_string())?;
+
+            // Secret Service has no expiration, so the deadline is an attribute
+            if let Some(at) = attributes.get("expires") {
+                if is_expired(at.parse().unwrap_or(0)) {
+                    let _ = item.delete();
+                    return Err("Password expired.".to_owned());
+                }
+            }
+
+            item.get_secret()
+                .map_err(|e| e.to_string())
+                .and_then(|pwd| String::from_utf8(pwd).map_err(|e| e.to_string()))
+                .map(Pwd::from)
+        })
+    }
+
+    pub fn set_password(&self, password: &str, ttl: Option<Duration>) -> Result<(), String> {
+        let ss = connect()?;
+        let collection = ss.get_default_collection().map_err(|e| e.to_string())?;
+
+        collection.ensure_unlocked().map_err(|e| e.to_string())?;
+
+        // Items are replaced only if all attributes match, but `expires` differs
+        let found = ss
+            .search_items(self.attributes())
+            .map_err(|e| e.to_string())?;
+
+        for item in found.unlocked.iter().chain(&found.locked) {
+            item.delete().map_err(|e| e.to_string())?;
+        }
+
+        let mut attributes = self.attributes();
+        attributes.insert("database", &self.database);
+        attributes.insert("application", crate::BIN_NAME);
+
+        let expires = ttl.map(|ttl| expires_at(ttl).to_string());
+        if let Some(at) = &expires {
+            attributes.insert("expires", at);
+        }
+
+        collection
+            .create_item(
+                &format!("{} master password for {}", crate::BIN_NAME, self.database),
+                attributes,
+                password.as_bytes(),
+                true, // replace the item having same attributes
+                "text/plain",
+            )
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn has_expiry(&self) -> bool {
+        connect()
+            .and_then(|ss| {
+                let found = ss
+                    .search_items(self.attributes())
+                    .map_err(|e| e.to_string())?;
+
+                // Attributes are readable without unlocking
+                let item = found.unlocked.first().or(found.locked.first());
+                Ok(item.and_then(|item| item.get_attributes().ok()))
+            })
+            .map(|attributes| attributes.is_some_and(|a| a.contains_key("expires")))
+            .unwrap_or(true)
+    }
+
+    pub fn delete_password(&self) -> Result<(), String> {
+        let ss = connect()?;
+
+        with_item(&ss, self.attributes(), |item| {
+            item.delete().map_err(|e| e.to_string())
+        })
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "service: {}, account: {}, database: {}",
+            self.service, self.account, self.database
+        )
+    }
+}
+
+fn connect() -> Result<SecretService<'static>, String> {
+    SecretService::connect(EncryptionType::Dh).map_err(|e| e.to_string())
+}
+
+/// Unlocks the item (which might prompt the user) before passing it on.
+fn with_item<T>(
+    ss: &SecretService,
+    attributes: HashMap<&str, &str>,
+    f: impl FnOnce(&Item) -> Result<T, String>,
+) -> Result<T, String> {
+    let found = ss.search_items(attributes).map_err(|e| e.to_string())?;
+
+    if let Some(item) = found.unlocked.first() {
+        return f(item);
+    }
+
+    let item = found.locked.first().ok_or("No such item.")?;
+    debug!("unlocking secret service item");
+
+    item.unlock().map_err(|e| e.to_string())?;
+    f(item)
+}
+
+/// Needs a Secret Service provider with an unlocked default collection on the session bus, e.g.
+/// `dbus-run-session -- sh -c 'echo | gnome-keyring-daemon --unlock && cargo test -- --ignored'`
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each test has its own item, as they run in parallel.
+    fn secret(account: &str) -> Secret {
+        Secret::new(
+            format!("{}-test", crate::BIN_NAME),
+            account.to_owned(),
+            "test.kdbx".to_owned(),
+        )
+    }
+
+    #[test]
+    #[ignore]
+    fn set_get_delete() {
+        let secret = secret("set_get_delete");
+
+        secret.set_password("P@ssw0rd", None).unwrap();
+        assert!(!secret.has_expiry());
+        assert_eq!(&*secret.get_password().unwrap(), "P@ssw0rd");
+
+        // Replaced, not added
+        secret.set_password("N3wP@ss", None).unwrap();
+        assert_eq!(&*secret.get_password().unwrap(), "N3wP@ss");
+
+        secret.delete_password().unwrap();
+        assert!(secret.get_password().is_err());
+        assert!(secret.delete_password().is_err());
+    }
+
+    #[test]
+    #[ignore]
+    fn expires() {
+        let secret = secret("expires");
+
+        secret
+            .set_password("P@ssw0rd", Some(Duration::from_secs(600)))
+            .unwrap();
+        assert!(secret.has_expiry());
+        assert_eq!(&*secret.get_password().unwrap(), "P@ssw0rd");
+
+        // Setting again without TTL drops the `expires` attribute
+        secret.set_password("P@ssw0rd", None).unwrap();
+        assert!(!secret.has_expiry());
+
+        // Expired item is deleted once read
+        secret
+            .set_password("P@ssw0rd", Some(Duration::ZERO))
+            .unwrap();
+        assert!(secret.has_expiry());
+        assert_eq!(secret.get_password().unwrap_err(), "Password expired.");
+        assert!(secret.delete_password().is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{format::*, resolve::Resolver, utils::*, Args, Result};
+
+pub(super) fn run(args: Args) -> Result<()> {
+    let format = Format::parse(args.flag_format.as_deref())?;
+    let columns = parse_columns(args.flag_columns.as_deref())?;
+
+    let dbs = open_databases(&args.databases)?;
+    let all = Entries::new(&dbs, &args.databases);
+
+    let query = args.arg_entry.as_ref().map(String::as_ref);
+
+    let entries = match query {
+        Some(query) => all.search(query, args.flag_url)?,
+        None => all.iter().collect(),
+    };
+
+    // Let scripts tell apart an empty database and a typo in the query
+    if entries.is_empty() {
+        if let Some(query) = query {
+            return Err(format!("No match for {}.", query).into());
+        }
+    }
+
+    if format != Format::Text {
+        return print_entries(&all, &entries, &format, args.flag_reveal);
+    }
+
+    for &(db, entry) in &entries {
+        let resolver = Resolver::for_display(all.of(db), args.flag_reveal);
+        let mut line = all.path(db, entry, args.flag_no_group);
+
+        for column in &columns {
+            let field = match column {
+                Column::UserName => "username",
+                Column::Url => "url",
+            };
+
+            line.push('\t');
+            line.push_str(&resolver.field(entry, Some(field)).unwrap_or_default());
+        }
+
+        wout!("{}", line);
+    }
+
+    Ok(())
+}
+
+enum Column {
+    UserName,
+    Url,
+}
+
+fn parse_columns(columns: Option<&str>) -> Result<Vec<Column>> {
+    columns
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|c| match &*c.to_lowercase() {
+            "username" | "user" => Ok(Column::UserName),
+            "url" => Ok(Column::Url),
+            _ => Err(format!("Unknown column `{}`. Use `username` or `url`.", c).into()),
+        })
+        .collect()
+}
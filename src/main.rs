@@ -1,9 +1,22 @@
 #[macro_use]
 mod utils;
+mod agent;
 mod clip;
+mod clipboard;
+mod config;
+mod credential;
+mod entry;
+mod exec;
+mod format;
+mod generate;
 mod keyring;
+mod list;
+mod otp;
+mod prompt;
 mod pwd;
+mod resolve;
 mod show;
+mod site;
 mod stdin;
 
 use docopt::Docopt;
@@ -25,31 +38,111 @@ BIN_NAME BIN_VERSION
     KeePass KDBX4 password reader.
 
 Usage:
-    BIN_NAME [options] [<command>] [<entry>]
+    BIN_NAME [options] [-d <file>]... [-k <keyfile>]... [<command>] [<entry>]
+    BIN_NAME [options] [-d <file>]... [-k <keyfile>]... exec <args>...
     BIN_NAME --help
 
 Commands:
-    clip     Copy password and clear clipboard after specified amount of time.
+    clip     Copy password (or a field chosen with `--field`) and clear clipboard
+             after specified amount of time.
              This is default command if no other provided.
 
     info     Display entry's info. Alias `show`.
 
+    otp      Copy entry's current TOTP code and clear clipboard when it expires.
+             Reads `otp`, `TimeOtp-*` or `TOTP Seed` and `TOTP Settings` fields.
+
+    generate Generate a random password or passphrase and copy it to clipboard.
+             Alias `gen`.
+
+    exec     Run a program with entries' passwords (or `--field`) in its
+             environment: `exec -e VAR=<entry>... [--] <program> [<args>...]`.
+             Secrets are never printed.
+
+    git-credential
+             Git credential helper: `git-credential get` prints username and
+             password of the entry whose URL matches the requested protocol,
+             host and path. `store` and `erase` are ignored. Needs the password
+             in the keyring (`-p`) or `--pinentry` as STDIN belongs to git.
+
+    list     Print matching entries one per line without prompting. Alias `ls`.
+
+    agent    Unlock the databases once and serve them to other commands, which
+             then need no password, until `--agent-timeout` passes without
+             a request. Runs in the foreground, e.g. `BIN_NAME --pinentry agent &`.
+
 Options:
-    -d, --database <file>       KDBX file path.
+    --profile <name>            Use named profile from the config file.
+    -d, --database <file>       KDBX file path. Repeat to search several databases.
     -k, --key-file <keyfile>    Path to the key file unlocking the database.
+                                Several key files go to databases in the same order.
     -p, --use-keyring           Store password for the database in the OS's keyring.
+    --keyring-backend <name>    Where `--use-keyring` keeps passwords: `native` (Keychain
+                                on macOS, session keyring on Linux) or `secret-service`
+                                (GNOME Keyring, KWallet, etc. on Linux).
+    --keyring-ttl <duration>    Forget the stored password after e.g. `30m`, `8h` or `1d`.
+                                Default is to keep it until removed or logged out.
+    --pinentry                  Ask for the password with a pinentry or askpass program
+                                instead of the terminal. Default when there is neither
+                                terminal nor pipe, e.g. when started by a hotkey.
+    --pinentry-program <path>   Pinentry to use, e.g. `pinentry-gnome3`. Otherwise
+                                SSH_ASKPASS or `pinentry` from PATH is used.
+    --password-fd <n>           Read the password from file descriptor <n> instead of
+                                STDIN, e.g. `3< <(pass show kdbx)`.
+    --password-file <path>      Read the password from a file (should be `chmod 600`).
+    --password-env <var>        Read the password from environment variable <var>.
+                                It's removed before `exec` runs the program.
+    --agent-timeout <duration>  How long `agent` keeps databases unlocked since the last
+                                request, e.g. `30m` or `8h`. Default to 15m.
     -P, --remove-key            Remove database's password from OS's keyring and exit.
     -G, --no-group              Show entries without group(s).
     -v, --preview               Preview entry during picking.
     -f, --full-screen           Use all available screen for picker.
-    -t, --timeout <seconds>     Timeout in seconds before clearing the clipboard.
-                                Default to DEFAULT_TIMEOUT seconds. 0 means no clean-up.
+    -u, --url                   Treat <entry> as a URL and match it against entries'
+                                URL and `KP2A_URL*` fields: exact host first, then
+                                the same domain, then `*.domain` wildcards. Only
+                                an exact host match is taken without the picker.
+    -F, --field <name>          Copy or print the field instead of the password:
+                                `username`, `url`, `notes` or a custom field name.
+    -t, --timeout <seconds>     Timeout in seconds before clearing the clipboard or
+                                restoring what was copied before, unless something
+                                else has been copied since. Default to DEFAULT_TIMEOUT
+                                seconds. 0 means no clean-up.
+    --background                Return right away and let a detached process clear the
+                                clipboard, even if the terminal is closed meanwhile.
+    --clipboard <backend>       Where to copy: `native` (macOS, X11), `wayland` (wl-copy),
+                                `xclip`, `xsel`, `osc52` (terminal escape sequence,
+                                works over SSH) or `tmux`. Detected from WAYLAND_DISPLAY,
+                                DISPLAY, TMUX and SSH_TTY by default. Only `native` hides
+                                secrets from clipboard managers' history.
+    -l, --length <n>            Length of generated password. Default to DEFAULT_LENGTH.
+    --charset <classes>         Character classes of generated password, comma
+                                separated `lower`, `upper`, `digits`, `symbols`.
+                                Default to all of them.
+    -x, --no-lookalike          Exclude look-alike characters (e.g. `l`, `1`, `O`, `0`).
+    -w, --words <n>             Generate a passphrase of <n> words instead.
+    --wordlist <file>           Diceware (e.g. EFF) or a word per line list.
+                                Default to DEFAULT_WORDLIST.
+    -c, --columns <columns>     Extra tab separated columns to print with `list`.
+                                Comma separated `username` and `url`.
+    --format <format>           Output format of `show` and `list`: `text`, `json`,
+                                `yaml` or `tsv`. Default to `text`.
+    --reveal                    Include passwords in `json`, `yaml` and `tsv` output
+                                instead of masking them.
     -h, --help
     -V, --version
 
 Environment variables:
     ENV_VAR_NAME                 Set default arguments (see examples).
 
+Files:
+    $XDG_CONFIG_HOME/BIN_NAME/config.toml
+                                Named profiles with default arguments. Command line
+                                takes precedence over a profile and a profile over
+                                ENV_VAR_NAME. `default` key selects a profile
+                                when `--profile` is not given. `profiles` key lists
+                                other profiles to search their databases at once.
+
 Examples:
     Open a database and copy password to the clipboard after selection:
       $ BIN_NAME --database /root/secrets.kdbx
@@ -57,6 +150,24 @@ Examples:
     Set default database, secret file and options via environment variable:
       export ENV_VAR_NAME=\"-d$HOME/my.kdbx -k$HOME/.secret -pGt7\"
 
+    Describe profiles in config.toml and pick one (paths may contain spaces):
+      default = \"personal\"
+      [profiles.personal]
+      database = \"~/My Vault.kdbx\"
+      use_keyring = true
+      keyring_backend = \"secret-service\"
+      keyring_ttl = \"8h\"
+      [profiles.work]
+      database = \"~/work/team.kdbx\"
+      key_file = \"~/work/team.key\"
+      timeout = 7
+      [profiles.all]
+      profiles = [\"personal\", \"work\"]
+      $ BIN_NAME --profile work gitlab
+
+    Search personal and team databases at once:
+      $ BIN_NAME -d ~/my.kdbx -d ~/team.kdbx github
+
     Display selector and then print entry's info:
       $ BIN_NAME show
 
@@ -66,11 +177,42 @@ Examples:
     `clip` command name can be omitted:
       $ BIN_NAME gmail
 
+    Feed entry's fields to other tools:
+      $ BIN_NAME --format json show github.com | jq -r .username
+
+    Generate a 6 words passphrase:
+      $ BIN_NAME -w6 --wordlist ~/eff_large_wordlist.txt gen
+
+    Pass secrets to a script without exposing them in shell history:
+      $ BIN_NAME exec -e DB_PASS=prod-db -e API_KEY=stripe -- ./deploy.sh
+
+    Let git fetch credentials from the database:
+      $ git config --global credential.helper '!BIN_NAME -p -d ~/my.kdbx git-credential'
+
+    Copy a password from a window manager's hotkey, asking with a GUI pinentry:
+      bindsym $mod+p exec BIN_NAME --pinentry clip github
+
+    Print TOTP code for an entry:
+      $ BIN_NAME otp github | cat
+
+    List all entries with their usernames and URLs:
+      $ BIN_NAME -c username,url ls
+
+    Print password of the login page's entry:
+      $ BIN_NAME --url https://gitlab.example.com/users/sign_in | cat
+
+    Copy username and then password:
+      $ BIN_NAME -F username github
+      $ BIN_NAME github
+
     Print password to STDOUT:
       $ BIN_NAME github.com | cat
 
     Read password from STDIN:
       $ cat /mnt/usb/key | kp
+
+    Keep STDIN for data while the password comes from another descriptor:
+      $ BIN_NAME --password-fd 3 exec -e TOKEN=api -- ./upload.sh < data.csv 3< /mnt/usb/key
 ";
 
 static CANCEL: atomic::AtomicBool = atomic::AtomicBool::new(false);
@@ -81,13 +223,29 @@ type Result<T> = result::Result<T, Box<dyn error::Error>>;
 fn main() {
     env_logger::init();
 
-    set_ctrlc_handler();
+    pwd::harden_process();
+
+    if env::var_os(clipboard::WIPER_VAR_NAME.to_uppercase()).is_some() {
+        clipboard::run_wiper();
+    }
+
+    if env::var_os(clipboard::SERVER_VAR_NAME.to_uppercase()).is_some() {
+        clipboard::run_server();
+    }
 
     let args = get_args();
 
+    set_ctrlc_handler(args.clipboard);
+
     if let Err(err) = match args.arg_command {
         Command::Clip => clip::run(args),
         Command::Show => show::run(args),
+        Command::List => list::run(args),
+        Command::Otp => otp::run(args),
+        Command::Generate => generate::run(args),
+        Command::Exec => exec::run(args),
+        Command::GitCredential => credential::run(args),
+        Command::Agent => agent::run(args),
         Command::Unknown(cmd) => {
             Err(format!("Unknown command `{}`. Use `--help` to get more info.", cmd).into())
         }
@@ -101,6 +259,12 @@ fn main() {
 enum Command {
     Clip,
     Show,
+    List,
+    Otp,
+    Generate,
+    Exec,
+    GitCredential,
+    Agent,
     Unknown(String),
 }
 
@@ -108,16 +272,43 @@ enum Command {
 struct Args {
     arg_command: Command,
     arg_entry: Option<String>,
+    arg_args: Vec<String>,
+    cmd_exec: bool,
     flag_timeout: Option<u8>,
+    flag_clipboard: Option<String>,
+    flag_background: bool,
+    flag_field: Option<String>,
     flag_no_group: bool,
     flag_preview: bool,
     flag_full_screen: bool,
+    flag_length: Option<usize>,
+    flag_charset: Option<String>,
+    flag_no_lookalike: bool,
+    flag_words: Option<usize>,
+    flag_wordlist: Option<PathBuf>,
+    flag_columns: Option<String>,
+    flag_format: Option<String>,
+    flag_reveal: bool,
+    flag_url: bool,
     flag_use_keyring: bool,
+    flag_keyring_backend: Option<String>,
+    flag_keyring_ttl: Option<String>,
+    flag_pinentry: bool,
+    flag_pinentry_program: Option<PathBuf>,
+    flag_password_fd: Option<i32>,
+    flag_password_file: Option<PathBuf>,
+    flag_password_env: Option<String>,
+    flag_agent_timeout: Option<String>,
     flag_remove_key: bool,
-    flag_database: Option<PathBuf>,
-    flag_key_file: Option<PathBuf>,
+    flag_profile: Option<String>,
+    flag_database: Vec<PathBuf>,
+    flag_key_file: Vec<PathBuf>,
     flag_help: bool,
     flag_version: bool,
+    #[serde(skip)]
+    databases: Vec<utils::Source>,
+    #[serde(skip)]
+    clipboard: clipboard::Backend,
 }
 
 impl Args {
@@ -149,6 +340,8 @@ impl Args {
 fn get_args() -> Args {
     let usage = USAGE
         .replace("DEFAULT_TIMEOUT", &DEFAULT_TIMEOUT.to_string())
+        .replace("DEFAULT_LENGTH", &generate::DEFAULT_LENGTH.to_string())
+        .replace("DEFAULT_WORDLIST", generate::DEFAULT_WORDLIST)
         .replace("ENV_VAR_NAME", &ENV_VAR_NAME.to_uppercase())
         .replace("BIN_NAME", BIN_NAME)
         .replace("BIN_VERSION", &version());
@@ -162,6 +355,10 @@ fn get_args() -> Args {
     debug!("cmd line: {:#?}", cmd);
 
     cmd.arg_command = match cmd.arg_command {
+        // Has its own usage pattern to keep the program's arguments
+        // $ kp exec -e TOKEN=github -- ./script.sh
+        _ if cmd.cmd_exec => Command::Exec,
+
         Command::Unknown(ref command) if command.is_empty() => Command::Clip,
 
         // Unknown command becomes entry title.
@@ -176,28 +373,122 @@ fn get_args() -> Args {
         c => c,
     };
 
+    let profile = config::load_profile(cmd.flag_profile.as_deref().or(env.flag_profile.as_deref()))
+        .unwrap_or_else(|e| {
+            werr!("{}", e);
+            process::exit(1);
+        })
+        .unwrap_or_default();
+    debug!("profile: {:#?}", profile);
+
     cmd.flag_timeout = cmd
         .flag_timeout
+        .or(profile.timeout)
         .or(env.flag_timeout)
         .or(Some(DEFAULT_TIMEOUT))
         .filter(|&t| t != 0);
 
-    cmd.flag_use_keyring |= env.flag_use_keyring;
-    cmd.flag_no_group |= env.flag_no_group;
-    cmd.flag_preview |= env.flag_preview;
-    cmd.flag_full_screen |= env.flag_full_screen;
-    cmd.flag_key_file = cmd.flag_key_file.or(env.flag_key_file);
-    cmd.flag_database = cmd.flag_database.or(env.flag_database).or_else(|| {
+    cmd.flag_clipboard = cmd
+        .flag_clipboard
+        .or_else(|| profile.clipboard.clone())
+        .or(env.flag_clipboard);
+    cmd.clipboard = match cmd.flag_clipboard.as_deref() {
+        Some(name) => name.parse().unwrap_or_else(|e| {
+            werr!("{}", e);
+            process::exit(1);
+        }),
+        None => clipboard::Backend::detect(),
+    };
+    cmd.flag_background |= profile.background || env.flag_background;
+    cmd.flag_use_keyring |= profile.use_keyring || env.flag_use_keyring;
+    cmd.flag_keyring_backend = cmd
+        .flag_keyring_backend
+        .or_else(|| profile.keyring_backend.clone())
+        .or(env.flag_keyring_backend);
+    cmd.flag_keyring_ttl = cmd
+        .flag_keyring_ttl
+        .or_else(|| profile.keyring_ttl.clone())
+        .or(env.flag_keyring_ttl);
+    cmd.flag_agent_timeout = cmd
+        .flag_agent_timeout
+        .or_else(|| profile.agent_timeout.clone())
+        .or(env.flag_agent_timeout);
+    cmd.flag_no_group |= profile.no_group || env.flag_no_group;
+    cmd.flag_preview |= profile.preview || env.flag_preview;
+    cmd.flag_full_screen |= profile.full_screen || env.flag_full_screen;
+    cmd.flag_pinentry |= profile.pinentry || env.flag_pinentry;
+    cmd.flag_pinentry_program = cmd
+        .flag_pinentry_program
+        .or_else(|| profile.pinentry_program.clone())
+        .or(env.flag_pinentry_program);
+    // Password sources exclude each other, so any on the command line replaces the defaults
+    if cmd.flag_password_fd.is_none()
+        && cmd.flag_password_file.is_none()
+        && cmd.flag_password_env.is_none()
+    {
+        cmd.flag_password_fd = env.flag_password_fd;
+        cmd.flag_password_file = profile.password_file.clone().or(env.flag_password_file);
+        cmd.flag_password_env = profile.password_env.clone().or(env.flag_password_env);
+    }
+    cmd.flag_reveal |= env.flag_reveal;
+    cmd.flag_no_lookalike |= env.flag_no_lookalike;
+    cmd.flag_length = cmd.flag_length.or(env.flag_length);
+    cmd.flag_charset = cmd.flag_charset.or(env.flag_charset);
+    cmd.flag_words = cmd.flag_words.or(env.flag_words);
+    cmd.flag_wordlist = cmd.flag_wordlist.or(env.flag_wordlist);
+    cmd.flag_field = cmd.flag_field.or(env.flag_field);
+    cmd.flag_columns = cmd.flag_columns.or(env.flag_columns);
+    cmd.flag_format = cmd.flag_format.or(env.flag_format);
+    cmd.databases = get_sources(&cmd, profile, env.flag_database, env.flag_key_file)
+        .unwrap_or_else(|e| {
+            werr!("{}", e);
+            process::exit(1);
+        });
+
+    // Window managers start programs with neither terminal nor pipe
+    if cmd.flag_pinentry || prompt::no_input() {
+        let helper = prompt::Helper::find(cmd.flag_pinentry_program.as_deref());
+
+        if helper.is_none() && cmd.flag_pinentry {
+            werr!("No pinentry found. Use `--pinentry-program` or set SSH_ASKPASS.");
+            process::exit(1);
+        }
+
+        for src in &mut cmd.databases {
+            src.prompt = helper.clone();
+        }
+    }
+
+    // Generator is the only command not touching a database
+    let needs_database = cmd.flag_remove_key || !matches!(cmd.arg_command, Command::Generate);
+
+    if needs_database && cmd.databases.is_empty() {
         werr!("No database file were found. Use `--help` to get more info.");
         process::exit(1);
-    });
+    }
+
+    // The picker can't search by URL
+    let picks_entry = matches!(
+        cmd.arg_command,
+        Command::Clip | Command::Show | Command::Otp
+    );
+
+    if cmd.flag_url && picks_entry && cmd.arg_entry.is_none() {
+        werr!("No URL to match. Use `--help` to get more info.");
+        process::exit(1);
+    }
 
     if cmd.flag_remove_key {
-        let dbfile = cmd.flag_database.as_deref().unwrap();
+        for (dbfile, backend) in cmd.databases.iter().map(|src| (&src.path, src.keyring)) {
+            if let Some(keyring) = keyring::Keyring::from_db_path(dbfile, backend) {
+                if let Err(msg) = keyring.delete_password() {
+                    werr!("No key removed for `{}`. {}", dbfile.to_string_lossy(), msg);
+                }
+            }
 
-        if let Some(keyring) = keyring::Keyring::from_db_path(dbfile) {
-            if let Err(msg) = keyring.delete_password() {
-                werr!("No key removed for `{}`. {}", dbfile.to_string_lossy(), msg);
+            // Not migrated yet
+            if let Some(legacy) = keyring::Keyring::legacy(dbfile, backend) {
+                let _ = legacy.delete_password();
             }
         }
 
@@ -208,15 +499,135 @@ fn get_args() -> Args {
     cmd
 }
 
-fn set_ctrlc_handler() {
-    if let Err(e) = ctrlc::set_handler(|| {
+/// Pairs databases with key files taking the first of command line, profile and env var.
+fn get_sources(
+    cmd: &Args,
+    profile: config::Profile,
+    env_databases: Vec<PathBuf>,
+    env_key_files: Vec<PathBuf>,
+) -> Result<Vec<utils::Source>> {
+    let password = password_source(cmd)?;
+
+    let mut sources = if cmd.flag_database.is_empty() && !profile.members.is_empty() {
+        profile
+            .members
+            .into_iter()
+            .map(|(name, p)| {
+                let path = p
+                    .database
+                    .ok_or_else(|| format!("No database in profile `{}`.", name))?;
+
+                Ok(utils::Source {
+                    label: Some(name),
+                    path,
+                    key_file: p.key_file,
+                    use_keyring: p.use_keyring || cmd.flag_use_keyring,
+                    keyring: keyring_backend(
+                        p.keyring_backend
+                            .as_deref()
+                            .or(cmd.flag_keyring_backend.as_deref()),
+                    )?,
+                    keyring_ttl: keyring_ttl(
+                        p.keyring_ttl.as_deref().or(cmd.flag_keyring_ttl.as_deref()),
+                    )?,
+                    prompt: None,
+                    password: password.clone(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        let databases = Some(cmd.flag_database.clone())
+            .filter(|d| !d.is_empty())
+            .or_else(|| profile.database.map(|d| vec![d]))
+            .unwrap_or(env_databases);
+
+        let key_files = Some(cmd.flag_key_file.clone())
+            .filter(|k| !k.is_empty())
+            .or_else(|| profile.key_file.map(|k| vec![k]))
+            .unwrap_or(env_key_files);
+
+        // A single key file unlocks every database
+        if key_files.len() > 1 && key_files.len() != databases.len() {
+            return Err(format!(
+                "{} key files given for {} databases.",
+                key_files.len(),
+                databases.len()
+            )
+            .into());
+        }
+
+        let keyring = keyring_backend(cmd.flag_keyring_backend.as_deref())?;
+        let keyring_ttl = keyring_ttl(cmd.flag_keyring_ttl.as_deref())?;
+
+        databases
+            .into_iter()
+            .enumerate()
+            .map(|(idx, path)| utils::Source {
+                label: path.file_stem().map(|s| s.to_string_lossy().into_owned()),
+                path,
+                key_file: key_files.get(idx).or_else(|| key_files.first()).cloned(),
+                use_keyring: cmd.flag_use_keyring,
+                keyring,
+                keyring_ttl,
+                prompt: None,
+                password: password.clone(),
+            })
+            .collect()
+    };
+
+    // Labels are only needed to tell databases apart
+    if sources.len() == 1 {
+        sources[0].label = None;
+    }
+
+    Ok(sources)
+}
+
+fn password_source(cmd: &Args) -> Result<Option<stdin::PasswordSource>> {
+    let given = [
+        cmd.flag_password_fd.is_some(),
+        cmd.flag_password_file.is_some(),
+        cmd.flag_password_env.is_some(),
+    ];
+
+    if given.iter().filter(|&&g| g).count() > 1 {
+        return Err(
+            "Use only one of `--password-fd`, `--password-file` and `--password-env`.".into(),
+        );
+    }
+
+    // Taken before any database is opened, as the keyring may answer first
+    if let Some(var) = cmd.flag_password_env.clone() {
+        return Ok(Some(stdin::PasswordSource::from_env(var)?));
+    }
+
+    Ok(cmd
+        .flag_password_fd
+        .map(stdin::PasswordSource::Fd)
+        .or_else(|| {
+            cmd.flag_password_file
+                .clone()
+                .map(stdin::PasswordSource::File)
+        }))
+}
+
+fn keyring_backend(name: Option<&str>) -> Result<keyring::Backend> {
+    Ok(name.map(str::parse).transpose()?.unwrap_or_default())
+}
+
+fn keyring_ttl(val: Option<&str>) -> Result<Option<time::Duration>> {
+    val.map(utils::parse_duration).transpose()
+}
+
+fn set_ctrlc_handler(clipboard: clipboard::Backend) {
+    if let Err(e) = ctrlc::set_handler(move || {
         CANCEL.store(true, atomic::Ordering::SeqCst);
         STDIN.reset_tty();
 
         // allow gracefully finish any cancellable loop
         thread::sleep(time::Duration::from_millis(2 * 1_000 / CANCEL_RQ_FREQ));
 
-        let _ = utils::set_clipboard(None);
+        let _ = clipboard.restore();
         process::exit(1);
     }) {
         warn!("unable to setup Ctrl+C handler: {}", e);
@@ -241,7 +652,9 @@ impl<'de> Visitor<'de> for CommandVisitor {
     type Value = Command;
 
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("`clip` or `show` commands")
+        formatter.write_str(
+            "`clip`, `show`, `list`, `otp`, `generate`, `exec`, `git-credential` or `agent` commands",
+        )
     }
 
     fn visit_str<E>(self, s: &str) -> result::Result<Self::Value, E>
@@ -251,6 +664,12 @@ impl<'de> Visitor<'de> for CommandVisitor {
         Ok(match &*s.to_lowercase() {
             "clip" | "c" => Command::Clip,
             "show" | "s" | "info" | "i" => Command::Show,
+            "list" | "ls" => Command::List,
+            "otp" | "totp" => Command::Otp,
+            "generate" | "gen" => Command::Generate,
+            "exec" => Command::Exec,
+            "git-credential" => Command::GitCredential,
+            "agent" => Command::Agent,
             cmd => Command::Unknown(cmd.to_owned()),
         })
     }
@@ -0,0 +1,386 @@
+use crate::{clip::copy, entry::Entry, resolve::Resolver, utils::*, Args, Result};
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use url::Url;
+
+use log::*;
+
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const STEAM_CHARS: &[u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+const STEAM_DIGITS: u32 = 5;
+
+pub(super) fn run(args: Args) -> Result<()> {
+    let dbs = open_databases(&args.databases)?;
+    let entries = Entries::new(&dbs, &args.databases);
+
+    let query = args.arg_entry.as_ref().map(String::as_ref);
+
+    // Only a single title or exact URL match is taken without the user seeing it
+    let found = match query {
+        Some(query) => entries.search_unattended(query, args.flag_url)?,
+        None => Vec::new(),
+    };
+
+    if let [(db, entry)] = found.as_slice() {
+        let resolver = Resolver::new(entries.of(*db));
+
+        // Print the code to stdout when pipe used
+        // e.g. `kp otp example.com | cat`
+        if !is_tty(io::stdout()) {
+            let totp = Totp::from_entry(entry, &resolver)?;
+            put!("{}", totp.code(now()));
+            return Ok(());
+        }
+
+        return clip(entry, &resolver, &args);
+    }
+
+    if !is_tty(io::stdout()) {
+        return Err(format!("No single match for {}.", query.unwrap_or("[empty]")).into());
+    }
+
+    // URL matches can't be narrowed down by typing, so offer only them, wider ones included
+    let (items, query) = match (args.flag_url, query) {
+        (true, Some(url)) => match entries.search(url, true)? {
+            found if found.is_empty() => return Err(format!("No match for {}.", url).into()),
+            found => (found, None),
+        },
+        _ => (entries.iter().collect(), query),
+    };
+
+    if let Some((db, entry)) = skim(
+        &entries,
+        items,
+        query,
+        args.flag_no_group,
+        args.flag_preview,
+        args.flag_full_screen,
+    ) {
+        clip(entry, &Resolver::new(entries.of(db)), &args)?
+    }
+
+    Ok(())
+}
+
+fn clip(entry: &Entry, resolver: &Resolver, args: &Args) -> Result<()> {
+    let totp = Totp::from_entry(entry, resolver)?;
+    let now = now();
+
+    // The code is useless after it expires, so there is no point to keep it longer
+    let timeout = args.flag_timeout.map(|_| totp.remaining(now));
+
+    copy(&totp.code(now), timeout, args).map_err(|_| {
+        format!(
+            "Clipboard unavailable. Try use STDOUT, i.e. `kp otp '{}' | cat`.",
+            entry.title()
+        )
+        .into()
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// RFC 6238 time-based one-time password generator.
+struct Totp {
+    secret: Vec<u8>,
+    algorithm: Algorithm,
+    digits: u32,
+    period: u64,
+    steam: bool,
+}
+
+impl Totp {
+    /// Reads OTP settings from the entry.
+    ///
+    /// Supported formats are KeePassXC's `otp` field with `otpauth://` URI,
+    /// KeePass 2.47+ `TimeOtp-*` fields and legacy `TOTP Seed`/`TOTP Settings` pair.
+    fn from_entry(entry: &Entry, resolver: &Resolver) -> Result<Self> {
+        if let Some(uri) = field(entry, resolver, "otp")? {
+            return Totp::from_uri(&uri);
+        }
+
+        if let Some(secret) = field(entry, resolver, "TimeOtp-Secret-Base32")? {
+            let algorithm = match field(entry, resolver, "TimeOtp-Algorithm")?.as_deref() {
+                Some("HMAC-SHA-256") => Algorithm::Sha256,
+                Some("HMAC-SHA-512") => Algorithm::Sha512,
+                _ => Algorithm::Sha1,
+            };
+
+            let digits = field(entry, resolver, "TimeOtp-Length")?;
+            let period = field(entry, resolver, "TimeOtp-Period")?;
+
+            return Totp::new(
+                &secret,
+                algorithm,
+                parse_num(digits.as_deref(), 6)?,
+                parse_num(period.as_deref(), 30)?,
+                false,
+            );
+        }
+
+        if let Some(seed) = field(entry, resolver, "TOTP Seed")? {
+            // Settings are `<period>;<digits>` where digits might be `S` for Steam
+            let settings = field(entry, resolver, "TOTP Settings")?.unwrap_or_default();
+            let mut settings = settings.split(';');
+
+            let period = parse_num(settings.next(), 30)?;
+            let digits = settings.next().unwrap_or_default();
+            let steam = digits == "S";
+            let digits = if steam {
+                STEAM_DIGITS
+            } else {
+                parse_num(Some(digits), 6)?
+            };
+
+            return Totp::new(&seed, Algorithm::Sha1, digits, period, steam);
+        }
+
+        Err(format!("No OTP settings found in `{}`.", entry.title()).into())
+    }
+
+    fn from_uri(uri: &str) -> Result<Self> {
+        let uri = Url::parse(uri).map_err(|e| format!("Invalid OTP URI: {}.", e))?;
+
+        if uri.scheme() != "otpauth" || uri.host_str() != Some("totp") {
+            return Err("Only `otpauth://totp/` URIs are supported.".into());
+        }
+
+        let mut secret = None;
+        let mut algorithm = Algorithm::Sha1;
+        let mut digits = None;
+        let mut period = None;
+        let mut steam = false;
+
+        for (key, val) in uri.query_pairs() {
+            match &*key.to_lowercase() {
+                "secret" => secret = Some(val.into_owned()),
+                "algorithm" => {
+                    algorithm = match &*val.to_uppercase() {
+                        "SHA1" => Algorithm::Sha1,
+                        "SHA256" => Algorithm::Sha256,
+                        "SHA512" => Algorithm::Sha512,
+                        alg => return Err(format!("Unsupported OTP algorithm `{}`.", alg).into()),
+                    }
+                }
+                "digits" => digits = Some(val.into_owned()),
+                "period" => period = Some(val.into_owned()),
+                "encoder" => steam = val.eq_ignore_ascii_case("steam"),
+                _ => {}
+            }
+        }
+
+        let secret = secret.ok_or("No secret in OTP URI.")?;
+        let digits = if steam {
+            STEAM_DIGITS
+        } else {
+            parse_num(digits.as_deref(), 6)?
+        };
+
+        Totp::new(
+            &secret,
+            algorithm,
+            digits,
+            parse_num(period.as_deref(), 30)?,
+            steam,
+        )
+    }
+
+    fn new(
+        secret: &str,
+        algorithm: Algorithm,
+        digits: u32,
+        period: u64,
+        steam: bool,
+    ) -> Result<Self> {
+        // Secrets are often written in groups and lower case, e.g. `jbsw y3dp`
+        let secret = secret
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '=')
+            .collect::<String>()
+            .to_uppercase();
+
+        let secret = base32::decode(base32::Alphabet::RFC4648 { padding: false }, &secret)
+            .filter(|s| !s.is_empty())
+            .ok_or("OTP secret is not a valid base32 string.")?;
+
+        if !(1..=10).contains(&digits) {
+            return Err(format!("Unsupported number of OTP digits `{}`.", digits).into());
+        }
+
+        if period == 0 {
+            return Err("OTP period must be greater than zero.".into());
+        }
+
+        debug!(
+            "totp: {:?}, {} digits, {}s period",
+            algorithm, digits, period
+        );
+
+        Ok(Totp {
+            secret,
+            algorithm,
+            digits,
+            period,
+            steam,
+        })
+    }
+
+    fn code(&self, now: u64) -> String {
+        let counter = (now / self.period).to_be_bytes();
+
+        let hash = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.secret, &counter),
+            Algorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.secret, &counter),
+            Algorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.secret, &counter),
+        };
+
+        // Dynamic truncation as described in RFC 4226
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let mut bin = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+
+        if self.steam {
+            let mut code = String::new();
+            for _ in 0..self.digits {
+                let idx = (bin % STEAM_CHARS.len() as u32) as usize;
+                code.push(STEAM_CHARS[idx] as char);
+                bin /= STEAM_CHARS.len() as u32;
+            }
+            return code;
+        }
+
+        let code = u64::from(bin) % 10u64.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    /// Seconds until the current code expires.
+    fn remaining(&self, now: u64) -> u64 {
+        self.period - now % self.period
+    }
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <M as hmac::digest::KeyInit>::new_from_slice(key).expect("HMAC accepts any key size");
+    mac.update(msg);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Value of the custom field if the entry has one.
+///
+/// Protected fields, which KeePassXC makes of `otp` and `TOTP Seed`, can't be decrypted
+/// by kdbx4, so they are reported instead of being taken for missing.
+fn field(entry: &Entry, resolver: &Resolver, name: &str) -> Result<Option<String>> {
+    match entry.prop(name) {
+        Some(_) => resolver.field(entry, Some(name)).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(val: Option<&str>, default: T) -> Result<T> {
+    match val.map(str::trim).filter(|v| !v.is_empty()) {
+        None => Ok(default),
+        Some(v) => v
+            .parse()
+            .map_err(|_| format!("Invalid OTP setting `{}`.", v).into()),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ASCII "12345678901234567890" repeated to the hash size, as in RFC 6238 Appendix B
+    const SHA1_SEED: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    const SHA256_SEED: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZA";
+    const SHA512_SEED: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQGEZDGNA";
+
+    /// Time and codes for SHA1, SHA256 and SHA512
+    const RFC6238: [(u64, [&str; 3]); 6] = [
+        (59, ["94287082", "46119246", "90693936"]),
+        (1111111109, ["07081804", "68084774", "25091201"]),
+        (1111111111, ["14050471", "67062674", "99943326"]),
+        (1234567890, ["89005924", "91819424", "93441116"]),
+        (2000000000, ["69279037", "90698825", "38618901"]),
+        (20000000000, ["65353130", "77737706", "47863826"]),
+    ];
+
+    #[test]
+    fn rfc6238_vectors() {
+        let algorithms = [
+            (Algorithm::Sha1, SHA1_SEED),
+            (Algorithm::Sha256, SHA256_SEED),
+            (Algorithm::Sha512, SHA512_SEED),
+        ];
+
+        for (idx, (algorithm, seed)) in algorithms.into_iter().enumerate() {
+            let totp = Totp::new(seed, algorithm, 8, 30, false).unwrap();
+
+            for (time, codes) in RFC6238 {
+                assert_eq!(totp.code(time), codes[idx], "{:?} at {}", algorithm, time);
+            }
+        }
+    }
+
+    #[test]
+    fn rfc6238_vectors_from_uri() {
+        for (idx, (name, seed)) in [
+            ("SHA1", SHA1_SEED),
+            ("SHA256", SHA256_SEED),
+            ("SHA512", SHA512_SEED),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let uri = format!(
+                "otpauth://totp/kp:test?secret={}&algorithm={}&digits=8&period=30",
+                seed, name
+            );
+            let totp = Totp::from_uri(&uri).unwrap();
+
+            for (time, codes) in RFC6238 {
+                assert_eq!(totp.code(time), codes[idx], "{} at {}", name, time);
+            }
+        }
+    }
+
+    #[test]
+    fn steam_vector() {
+        // Same as KeePassXC's tests
+        let totp = Totp::from_uri(
+            "otpauth://totp/kp:steam?secret=63BEDWCQZKTQWPESARIERL5DTTQFCJTK&encoder=steam",
+        )
+        .unwrap();
+
+        assert_eq!(totp.code(1511200518), "FR8RV");
+        assert_eq!(totp.code(1511200714), "9P3VP");
+    }
+
+    #[test]
+    fn remaining_seconds() {
+        let totp = Totp::new(SHA1_SEED, Algorithm::Sha1, 6, 30, false).unwrap();
+
+        assert_eq!(totp.remaining(59), 1);
+        assert_eq!(totp.remaining(60), 30);
+    }
+}
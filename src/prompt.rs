@@ -0,0 +1,231 @@
+use crate::pwd::Pwd;
+use crate::utils::find_program;
+use crate::Result;
+
+use log::*;
+
+use std::env;
+use std::ffi::CStr;
+use std::io::{BufRead, BufReader, Write};
+use std::mem::MaybeUninit;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// External program asking for the password when there is no terminal.
+#[derive(Debug, Clone)]
+pub enum Helper {
+    /// Talks Assuan protocol of GnuPG's pinentry
+    Pinentry(PathBuf),
+    /// Prints the password to stdout, e.g. `SSH_ASKPASS` programs
+    Askpass(PathBuf),
+}
+
+impl Helper {
+    /// Explicitly given pinentry program, then `SSH_ASKPASS`, then `pinentry` from `PATH`.
+    pub fn find(program: Option<&Path>) -> Option<Helper> {
+        if let Some(program) = program {
+            return Some(Helper::Pinentry(program.to_owned()));
+        }
+
+        if let Some(askpass) = env::var_os("SSH_ASKPASS").filter(|p| !p.is_empty()) {
+            return Some(Helper::Askpass(askpass.into()));
+        }
+
+        find_program("pinentry").map(Helper::Pinentry)
+    }
+
+    /// Asks for the password showing the error of the previous attempt, if any.
+    pub fn read_password(&self, desc: &str, error: Option<&str>) -> Result<Pwd> {
+        debug!("asking password with {:?}", self);
+
+        match self {
+            Helper::Pinentry(program) => pinentry(program, desc, error),
+            Helper::Askpass(program) => {
+                let prompt = match error {
+                    Some(error) => format!("{} {}", error, desc),
+                    None => desc.to_owned(),
+                };
+
+                askpass(program, &prompt)
+            }
+        }
+    }
+}
+
+/// True when stdin is neither a terminal nor a pipe or file to read from,
+/// e.g. `/dev/null` given by a window manager or a closed descriptor.
+pub fn no_input() -> bool {
+    let mut stat = MaybeUninit::<libc::stat>::uninit();
+
+    unsafe {
+        if libc::fstat(libc::STDIN_FILENO, stat.as_mut_ptr()) != 0 {
+            return true;
+        }
+
+        let mode = stat.assume_init().st_mode & libc::S_IFMT;
+        mode == libc::S_IFCHR && libc::isatty(libc::STDIN_FILENO) != 1
+    }
+}
+
+fn ttyname() -> Option<String> {
+    unsafe {
+        let name = libc::ttyname(libc::STDIN_FILENO);
+
+        if name.is_null() {
+            return None;
+        }
+
+        CStr::from_ptr(name).to_str().ok().map(str::to_owned)
+    }
+}
+
+fn askpass(program: &Path, prompt: &str) -> Result<Pwd> {
+    let output = Command::new(program)
+        .arg(prompt)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| format!("Can't run `{}`. {}", program.display(), e))?;
+
+    if !output.status.success() {
+        return Err("Password prompt cancelled.".into());
+    }
+
+    let mut pwd = String::from_utf8(output.stdout).map_err(|e| e.to_string())?;
+
+    while pwd.ends_with(['\n', '\r']) {
+        pwd.pop();
+    }
+
+    Ok(pwd.into())
+}
+
+/// See "The Pinentry Protocol" in GnuPG docs.
+fn pinentry(program: &Path, desc: &str, error: Option<&str>) -> Result<Pwd> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Can't run `{}`. {}", program.display(), e))?;
+
+    let mut input = child.stdin.take().expect("piped stdin");
+    let mut output = BufReader::new(child.stdout.take().expect("piped stdout"));
+
+    let mut commands = vec![
+        format!("SETTITLE {}", crate::BIN_NAME),
+        format!("SETDESC {}", escape(desc)),
+        "SETPROMPT Password:".to_owned(),
+    ];
+
+    // Let GUI pinentries find the display, others get the terminal.
+    // Wayland ones read `WAYLAND_DISPLAY` from the inherited environment.
+    if let Some(display) = env::var_os("DISPLAY").as_ref().and_then(|v| v.to_str()) {
+        commands.push(format!("OPTION display={}", display));
+    }
+
+    if let Some(tty) = ttyname() {
+        commands.push(format!("OPTION ttyname={}", tty));
+
+        if let Ok(term) = env::var("TERM") {
+            commands.push(format!("OPTION ttytype={}", term));
+        }
+    }
+
+    if let Some(error) = error {
+        commands.push(format!("SETERROR {}", escape(error)));
+    }
+
+    let mut pwd = None;
+
+    // Greeting comes before any command
+    let result = read_response(&mut output, &mut pwd).and_then(|greeting| {
+        if let Some(err) = greeting {
+            return Err(format!("Pinentry failed. {}", err).into());
+        }
+
+        // Older pinentries reject unknown options and commands, they only cost the looks
+        for command in &commands {
+            writeln!(input, "{}", command)?;
+
+            if let Some(err) = read_response(&mut output, &mut pwd)? {
+                debug!("pinentry: `{}` failed: {}", command, err);
+            }
+        }
+
+        writeln!(input, "GETPIN")?;
+
+        // Cancelled (83886179) or timed out
+        match read_response(&mut output, &mut pwd)? {
+            Some(_) => Err("Password prompt cancelled.".into()),
+            None => Ok(()),
+        }
+    });
+
+    let _ = writeln!(input, "BYE");
+    drop(input);
+    let _ = child.wait();
+
+    result?;
+
+    Ok(pwd.unwrap_or_default().into())
+}
+
+/// Reads lines until `OK` or `ERR`, collecting `D` data lines. Returns the error of `ERR`.
+fn read_response(output: &mut impl BufRead, data: &mut Option<String>) -> Result<Option<String>> {
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+
+        if output.read_line(&mut line)? == 0 {
+            return Err("Pinentry exited unexpectedly.".into());
+        }
+
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        match line.split_once(' ').unwrap_or((line, "")) {
+            ("OK", _) => return Ok(None),
+            ("D", val) => data
+                .get_or_insert_with(String::new)
+                .push_str(&unescape(val)),
+            ("ERR", err) => {
+                debug!("pinentry: ERR {}", err);
+                return Ok(Some(err.to_owned()));
+            }
+            // Status and comment lines
+            _ => {}
+        }
+    }
+}
+
+fn escape(val: &str) -> String {
+    val.replace('%', "%25")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+fn unescape(val: &str) -> String {
+    let mut bytes = Vec::with_capacity(val.len());
+    let mut rest = val.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail
+            .get(..2)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+
+        match (byte, hex) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
@@ -1,12 +1,51 @@
+use once_cell::sync::OnceCell;
+
 use log::*;
 
-use std::{convert::AsRef, ops::Deref, ptr, sync::atomic};
+use std::alloc::{self, Layout};
+use std::{convert::AsRef, fmt, io, ops::Deref, ptr, slice, str, sync::atomic};
+
+/// Secret string kept in locked memory, i.e. never swapped out, and zeroed on drop.
+pub struct Pwd {
+    ptr: ptr::NonNull<u8>,
+    len: usize,
+    layout: Layout,
+    locked: bool,
+}
+
+// Owns the buffer exclusively, same as `String`
+unsafe impl Send for Pwd {}
+unsafe impl Sync for Pwd {}
+
+impl Pwd {
+    fn new(val: &str) -> Self {
+        let page = page_size();
+
+        // Whole pages, so unlocking one secret can't unlock its neighbours
+        let size = val.len().max(1).div_ceil(page) * page;
+        let layout = Layout::from_size_align(size, page).expect("page aligned layout");
+
+        let ptr = ptr::NonNull::new(unsafe { alloc::alloc_zeroed(layout) })
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
 
-pub struct Pwd(String);
+        let locked = lock_memory(ptr.as_ptr(), size);
+
+        unsafe { ptr::copy_nonoverlapping(val.as_ptr(), ptr.as_ptr(), val.len()) };
+
+        Pwd {
+            ptr,
+            len: val.len(),
+            layout,
+            locked,
+        }
+    }
+}
 
 impl From<String> for Pwd {
-    fn from(pwd: String) -> Self {
-        Pwd(pwd)
+    fn from(mut pwd: String) -> Self {
+        let secret = Pwd::new(&pwd);
+        zero_memory(unsafe { pwd.as_bytes_mut() });
+        secret
     }
 }
 
@@ -14,7 +53,7 @@ impl Deref for Pwd {
     type Target = str;
 
     fn deref(&self) -> &Self::Target {
-        self.0.as_ref()
+        unsafe { str::from_utf8_unchecked(slice::from_raw_parts(self.ptr.as_ptr(), self.len)) }
     }
 }
 
@@ -24,19 +63,116 @@ impl AsRef<str> for Pwd {
     }
 }
 
+// Shows up in debug logs of the parsed arguments
+impl fmt::Debug for Pwd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Pwd(***)")
+    }
+}
+
+impl serde::Serialize for Pwd {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+// Passed from the agent, see `entry::Entry`
+impl<'de> serde::Deserialize<'de> for Pwd {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Pwd::from)
+    }
+}
+
 impl Drop for Pwd {
     fn drop(&mut self) {
         info!("zeroing password memory");
-        zero_memory(&mut self.0)
+
+        let size = self.layout.size();
+
+        unsafe {
+            zero_memory(slice::from_raw_parts_mut(self.ptr.as_ptr(), size));
+
+            if self.locked {
+                libc::munlock(self.ptr.as_ptr() as *const libc::c_void, size);
+            }
+
+            alloc::dealloc(self.ptr.as_ptr(), self.layout);
+        }
     }
 }
 
-fn zero_memory(pwd: &mut String) {
-    unsafe {
-        for byte in pwd.as_bytes_mut() {
-            ptr::write_volatile(byte, 0x00);
+/// Core dump limit before [`harden_process`], handed back to programs run by `exec`.
+static CORE_LIMIT: OnceCell<libc::rlimit> = OnceCell::new();
+
+/// Keeps secrets out of core dumps and away from other processes of the same user.
+pub fn harden_process() {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+
+    if unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut limit) } == 0 {
+        let _ = CORE_LIMIT.set(limit);
+    }
+
+    // Only the soft limit, the hard one can't be raised back
+    let no_core = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: limit.rlim_max,
+    };
+
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &no_core) } != 0 {
+        warn!("can't disable core dumps: {}", io::Error::last_os_error());
+    }
+
+    // Also forbids ptrace and reading /proc/<pid>/mem by non-root
+    #[cfg(target_os = "linux")]
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+        warn!(
+            "can't make process non-dumpable: {}",
+            io::Error::last_os_error()
+        );
+    }
+}
+
+/// Lets programs started in place of kp dump core as they would without it.
+pub fn restore_core_limit() {
+    if let Some(limit) = CORE_LIMIT.get() {
+        if unsafe { libc::setrlimit(libc::RLIMIT_CORE, limit) } != 0 {
+            warn!(
+                "can't restore core dump limit: {}",
+                io::Error::last_os_error()
+            );
         }
     }
+}
+
+fn lock_memory(ptr: *mut u8, size: usize) -> bool {
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::madvise(ptr as *mut libc::c_void, size, libc::MADV_DONTDUMP);
+    }
+
+    if unsafe { libc::mlock(ptr as *const libc::c_void, size) } == 0 {
+        return true;
+    }
+
+    // RLIMIT_MEMLOCK may be as small as 64 KiB, the secret is still zeroed on drop
+    debug!("can't lock password memory: {}", io::Error::last_os_error());
+    false
+}
+
+fn page_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+pub fn zero_memory(bytes: &mut [u8]) {
+    for byte in bytes {
+        unsafe { ptr::write_volatile(byte, 0x00) };
+    }
 
     atomic::fence(atomic::Ordering::SeqCst);
     atomic::compiler_fence(atomic::Ordering::SeqCst);
@@ -0,0 +1,432 @@
+use crate::{entry::Entry, pwd::Pwd, utils::*, Result};
+
+use url::Url;
+
+use log::*;
+
+// KeePass gives up on the same depth
+const MAX_DEPTH: usize = 12;
+
+/// Shown instead of passwords that aren't revealed.
+pub const MASK: &str = "********";
+
+/// Expands KeePass field references (`{REF:P@I:<uuid>}`) and
+/// placeholders (`{USERNAME}`, `{URL:HOST}`, `{S:Custom}`, etc.).
+///
+/// Unknown placeholders and broken references are left as they are, same as KeePass does.
+pub struct Resolver<'a> {
+    entries: &'a [Entry],
+    /// Passwords are expanded, otherwise masked
+    reveal: bool,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(entries: &'a [Entry]) -> Self {
+        Resolver {
+            entries,
+            reveal: true,
+        }
+    }
+
+    /// Resolver for printed fields, which masks passwords, even referenced ones, unless `reveal`.
+    pub fn for_display(entries: &'a [Entry], reveal: bool) -> Self {
+        Resolver { entries, reveal }
+    }
+
+    /// Returns the field's value (see [`entry_field`]) with everything expanded.
+    pub fn field(&self, entry: &Entry, field: Option<&str>) -> Result<String> {
+        self.field_impl(entry, field.unwrap_or("password"), &mut Vec::new())
+    }
+
+    /// Expands the text in context of the entry.
+    pub fn text(&self, entry: &Entry, text: &str) -> Result<String> {
+        self.text_impl(entry, text, &mut Vec::new())
+    }
+
+    fn field_impl(
+        &self,
+        entry: &Entry,
+        field: &str,
+        stack: &mut Vec<(String, String)>,
+    ) -> Result<String> {
+        let key = (entry.uuid().to_owned(), field.to_lowercase());
+
+        if !self.reveal && matches!(&*key.1, "password" | "pass") {
+            return Ok(MASK.to_owned());
+        }
+
+        if stack.contains(&key) {
+            return Err(format!(
+                "Circular reference in `{}` field of `{}`.",
+                field,
+                entry.title()
+            )
+            .into());
+        }
+
+        if stack.len() == MAX_DEPTH {
+            return Err(format!("Too deep references in `{}`.", entry.title()).into());
+        }
+
+        stack.push(key);
+        // Raw value may be the password, so it's wiped once expanded
+        let val = entry_field(entry, Some(field))
+            .and_then(|v| self.text_impl(entry, &Pwd::from(v), stack));
+        stack.pop();
+
+        val
+    }
+
+    fn text_impl(
+        &self,
+        entry: &Entry,
+        text: &str,
+        stack: &mut Vec<(String, String)>,
+    ) -> Result<String> {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find('{') {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+
+            match self.placeholder(entry, &rest[1..end], stack)? {
+                Some(val) => {
+                    result.push_str(&val);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    result.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        result.push_str(rest);
+        Ok(result)
+    }
+
+    fn placeholder(
+        &self,
+        entry: &Entry,
+        name: &str,
+        stack: &mut Vec<(String, String)>,
+    ) -> Result<Option<String>> {
+        let upper = name.to_uppercase();
+
+        if let Some(spec) = strip_prefix_ci(name, "REF:") {
+            return self.reference(spec, stack);
+        }
+
+        if let Some(field) = strip_prefix_ci(name, "S:") {
+            return match entry.prop(field) {
+                Some(_) => self.field_impl(entry, field, stack).map(Some),
+                None => Ok(None),
+            };
+        }
+
+        if let Some(part) = upper.strip_prefix("URL:") {
+            let url = self.optional_field(entry, "url", stack)?;
+            return Ok(url_part(&url, part));
+        }
+
+        Ok(Some(match &*upper {
+            "TITLE" => self.optional_field(entry, "title", stack)?,
+            "USERNAME" => self.optional_field(entry, "username", stack)?,
+            "PASSWORD" => self.optional_field(entry, "password", stack)?,
+            "URL" => self.optional_field(entry, "url", stack)?,
+            "NOTES" => self.optional_field(entry, "notes", stack)?,
+            "UUID" => uuid_hex(entry),
+            _ => return Ok(None),
+        }))
+    }
+
+    /// Standard fields might be omitted by some KeePass clients.
+    fn optional_field(
+        &self,
+        entry: &Entry,
+        field: &str,
+        stack: &mut Vec<(String, String)>,
+    ) -> Result<String> {
+        let prop = match field {
+            "title" => "Title",
+            "username" => "UserName",
+            "password" => "Password",
+            "url" => "URL",
+            _ => "Notes",
+        };
+
+        if entry.prop(prop).is_none() {
+            return Ok(String::new());
+        }
+
+        self.field_impl(entry, field, stack)
+    }
+
+    /// `<WantedField>@<SearchIn>:<Text>`, e.g. `U@I:46C9B1FFBD4ABC4BBB260C6190BAD20C`
+    fn reference(&self, spec: &str, stack: &mut Vec<(String, String)>) -> Result<Option<String>> {
+        let mut chars = spec.chars();
+
+        let (wanted, search_in, text) =
+            match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                (Some(wanted), Some('@'), Some(search_in), Some(':')) => (
+                    wanted.to_ascii_uppercase(),
+                    search_in.to_ascii_uppercase(),
+                    chars.as_str(),
+                ),
+                _ => return Ok(None),
+            };
+
+        let field = |code| match code {
+            'T' => Some("title"),
+            'U' => Some("username"),
+            'P' => Some("password"),
+            'A' => Some("url"),
+            'N' => Some("notes"),
+            _ => None,
+        };
+
+        let text = text.to_lowercase();
+        let mut target = None;
+
+        for e in self.entries {
+            let found = match (search_in, field(search_in)) {
+                ('I', _) => uuid_hex(e).eq_ignore_ascii_case(&text),
+                (_, Some(f)) => entry_field(e, Some(f))
+                    .map(|v| v.to_lowercase().contains(&text))
+                    .unwrap_or(false),
+                _ => {
+                    debug!("unsupported reference: {}", spec);
+                    return Ok(None);
+                }
+            };
+
+            if found {
+                target = Some(e);
+                break;
+            }
+        }
+
+        let target = match target {
+            Some(target) => target,
+            None => {
+                debug!("broken reference: {}", spec);
+                return Ok(None);
+            }
+        };
+        debug!("reference {} points to {}", spec, target.title());
+
+        match (wanted, field(wanted)) {
+            ('I', _) => Ok(Some(uuid_hex(target))),
+            (_, Some(f)) => self.optional_field(target, f, stack).map(Some),
+            _ => {
+                debug!("unsupported reference: {}", spec);
+                Ok(None)
+            }
+        }
+    }
+}
+
+fn strip_prefix_ci<'s>(val: &'s str, prefix: &str) -> Option<&'s str> {
+    val.get(..prefix.len())
+        .filter(|p| p.eq_ignore_ascii_case(prefix))
+        .map(|_| &val[prefix.len()..])
+}
+
+/// References use hex while the XML keeps UUIDs in base64.
+fn uuid_hex(entry: &Entry) -> String {
+    base64::decode(entry.uuid())
+        .unwrap_or_default()
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect()
+}
+
+fn url_part(url: &str, part: &str) -> Option<String> {
+    if part == "RMVSCM" {
+        return Some(url.find("://").map_or(url, |i| &url[i + 3..]).to_owned());
+    }
+
+    let url = match Url::parse(url) {
+        Ok(url) => url,
+        Err(_) => return Some(String::new()),
+    };
+
+    Some(match part {
+        "SCM" => url.scheme().to_owned(),
+        "HOST" => url.host_str().unwrap_or_default().to_owned(),
+        "PORT" => url
+            .port_or_known_default()
+            .map(|p| p.to_string())
+            .unwrap_or_default(),
+        "PATH" => url.path().to_owned(),
+        "QUERY" => url.query().map(|q| format!("?{}", q)).unwrap_or_default(),
+        "USERINFO" => match url.password() {
+            Some(pwd) => format!("{}:{}", url.username(), pwd),
+            None => url.username().to_owned(),
+        },
+        "USERNAME" => url.username().to_owned(),
+        "PASSWORD" => url.password().unwrap_or_default().to_owned(),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Entry numbered `id`, which is also its UUID's bytes, see [`uuid_of`].
+    fn entry(id: u8, notes: &str) -> Entry {
+        serde_json::from_value(serde_json::json!({
+            "uuid": base64::encode([id; 16]),
+            "title": format!("Entry {}", id),
+            "group": "Root",
+            "props": {
+                "Title": format!("Entry {}", id),
+                "UserName": format!("user{}", id),
+                "Password": "(protected)",
+                "URL": "https://user:pw@example.com:8443/login?next=%2F",
+                "Notes": notes,
+            },
+            "password": format!("secret{}", id),
+        }))
+        .unwrap()
+    }
+
+    fn uuid_of(id: u8) -> String {
+        format!("{:02X}", id).repeat(16)
+    }
+
+    #[test]
+    fn placeholders() {
+        let entries = [entry(
+            1,
+            "{USERNAME} / {PASSWORD} / {S:UserName} / {unknown}",
+        )];
+        let resolver = Resolver::new(&entries);
+
+        assert_eq!(
+            resolver.field(&entries[0], Some("notes")).unwrap(),
+            "user1 / secret1 / user1 / {unknown}"
+        );
+    }
+
+    #[test]
+    fn url_parts() {
+        let entries = [entry(1, "")];
+        let resolver = Resolver::new(&entries);
+        let part = |part| resolver.text(&entries[0], part).unwrap();
+
+        assert_eq!(part("{URL:SCM}"), "https");
+        assert_eq!(part("{URL:HOST}"), "example.com");
+        assert_eq!(part("{URL:PORT}"), "8443");
+        assert_eq!(part("{URL:PATH}"), "/login");
+        assert_eq!(part("{URL:QUERY}"), "?next=%2F");
+        assert_eq!(part("{URL:USERINFO}"), "user:pw");
+        assert_eq!(
+            part("{URL:RMVSCM}"),
+            "user:pw@example.com:8443/login?next=%2F"
+        );
+        assert_eq!(part("{URL:OTHER}"), "{URL:OTHER}");
+    }
+
+    #[test]
+    fn references() {
+        let entries = [
+            entry(
+                1,
+                &format!("{{REF:U@I:{}}} {{REF:P@T:entry 2}}", uuid_of(2)),
+            ),
+            entry(2, ""),
+        ];
+        let resolver = Resolver::new(&entries);
+
+        assert_eq!(
+            resolver.field(&entries[0], Some("notes")).unwrap(),
+            "user2 secret2"
+        );
+    }
+
+    #[test]
+    fn broken_references() {
+        let missing = format!("{{REF:U@I:{}}}", uuid_of(9));
+        let unsupported = format!("{{REF:X@I:{}}} {{REF:U@X:foo}}", uuid_of(1));
+        let entries = [entry(1, &format!("{} {}", missing, unsupported))];
+        let resolver = Resolver::new(&entries);
+
+        assert_eq!(
+            resolver.field(&entries[0], Some("notes")).unwrap(),
+            format!("{} {}", missing, unsupported)
+        );
+    }
+
+    #[test]
+    fn masked_passwords() {
+        let entries = [
+            entry(1, &format!("{{PASSWORD}} {{REF:P@I:{}}}", uuid_of(2))),
+            entry(2, ""),
+        ];
+
+        let masked = Resolver::for_display(&entries, false);
+        assert_eq!(
+            masked.field(&entries[0], Some("notes")).unwrap(),
+            format!("{} {}", MASK, MASK)
+        );
+        assert_eq!(masked.field(&entries[0], None).unwrap(), MASK);
+
+        let revealed = Resolver::for_display(&entries, true);
+        assert_eq!(
+            revealed.field(&entries[0], Some("notes")).unwrap(),
+            "secret1 secret2"
+        );
+    }
+
+    #[test]
+    fn circular_references() {
+        let entries = [
+            entry(1, &format!("{{REF:N@I:{}}}", uuid_of(2))),
+            entry(2, &format!("{{REF:N@I:{}}}", uuid_of(1))),
+        ];
+        let resolver = Resolver::new(&entries);
+
+        let err = resolver.field(&entries[0], Some("notes")).unwrap_err();
+        assert!(err.to_string().starts_with("Circular reference"));
+
+        let entries = [entry(1, "{NOTES}")];
+        assert!(Resolver::new(&entries)
+            .field(&entries[0], Some("notes"))
+            .is_err());
+    }
+
+    #[test]
+    fn max_depth() {
+        // Each entry's notes refer to the next one's
+        let chain = |len: u8| {
+            (1..=len)
+                .map(|id| match id {
+                    _ if id == len => entry(id, "end"),
+                    _ => entry(id, &format!("{{REF:N@I:{}}}", uuid_of(id + 1))),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let entries = chain(MAX_DEPTH as u8);
+        assert_eq!(
+            Resolver::new(&entries)
+                .field(&entries[0], Some("notes"))
+                .unwrap(),
+            "end"
+        );
+
+        let entries = chain(MAX_DEPTH as u8 + 1);
+        let err = Resolver::new(&entries)
+            .field(&entries[0], Some("notes"))
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Too deep references"));
+    }
+}
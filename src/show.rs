@@ -1,34 +1,52 @@
-use crate::{utils::*, Args, Result};
+use crate::{format::*, utils::*, Args, Result};
+
+use std::io;
 
 pub(super) fn run(args: Args) -> Result<()> {
-    let db = open_database(
-        args.flag_database.as_deref().unwrap(),
-        args.flag_key_file.as_deref(),
-        args.flag_use_keyring,
-    )?;
+    let format = Format::parse(args.flag_format.as_deref())?;
+
+    let dbs = open_databases(&args.databases)?;
+    let entries = Entries::new(&dbs, &args.databases);
 
     let query = args.arg_entry.as_ref().map(String::as_ref);
 
-    if let Some(query) = query {
-        if let [entry] = db.find(query).as_slice() {
-            wout!("-----");
-            put!("{}", entry);
-            wout!("-----");
-            return Ok(());
-        }
+    // Only a single title or exact URL match is taken without the user seeing it
+    let found = match query {
+        Some(query) => entries.search_unattended(query, args.flag_url)?,
+        None => Vec::new(),
+    };
+
+    if let [(db, entry)] = found.as_slice() {
+        return print_entry(&entries, *db, entry, &format, args.flag_reveal);
     }
 
-    if let Some(entry) = skim(
-        &db.entries(),
+    // URL matches can't be narrowed down by typing, so offer only them,
+    // wider ones included unless piped
+    let (items, query) = match (args.flag_url, query) {
+        (true, Some(url)) => {
+            let found = match is_tty(io::stdout()) {
+                true => entries.search(url, true)?,
+                false => found,
+            };
+
+            if found.is_empty() {
+                return Err(format!("No match for {}.", url).into());
+            }
+
+            (found, None)
+        }
+        _ => (entries.iter().collect(), query),
+    };
+
+    if let Some((db, entry)) = skim(
+        &entries,
+        items,
         query,
         args.flag_no_group,
         args.flag_preview,
         args.flag_full_screen,
     ) {
-        wout!("-----");
-        put!("{}", entry);
-        wout!("-----");
-        return Ok(());
+        return print_entry(&entries, db, entry, &format, args.flag_reveal);
     }
 
     Ok(())
@@ -0,0 +1,165 @@
+use crate::Result;
+
+use url::{Host, Url};
+
+use std::net::Ipv4Addr;
+
+/// How well an entry's URL matches the requested one, better goes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rank {
+    Exact,
+    Domain,
+    Wildcard,
+}
+
+/// Parses the requested URL, the scheme is optional (`gitlab.example.com/login`).
+pub fn parse(url: &str) -> Result<Url> {
+    Url::parse(url)
+        .ok()
+        .filter(Url::has_host)
+        .or_else(|| Url::parse(&format!("https://{}", url)).ok())
+        .filter(Url::has_host)
+        .ok_or_else(|| format!("Invalid URL `{}`.", url).into())
+}
+
+/// Compares hosts of the entry's URL field and the requested URL.
+///
+/// Entry's URL might be anything users put there, so its host is cut out
+/// by hand, which also keeps wildcards like `https://*.example.com` intact.
+pub fn rank(entry_url: &str, url: &Url) -> Option<Rank> {
+    let host = url.host_str()?.to_lowercase();
+    let entry_host = host_of(entry_url)?;
+
+    if entry_host == host {
+        return Some(Rank::Exact);
+    }
+
+    if let Some(domain) = entry_host.strip_prefix("*.") {
+        return host
+            .strip_suffix(domain)
+            .filter(|sub| sub.ends_with('.'))
+            .map(|_| Rank::Wildcard);
+    }
+
+    // IP addresses have no domains to share
+    if !matches!(url.host(), Some(Host::Domain(_))) || !is_domain(&entry_host) {
+        return None;
+    }
+
+    let domain = registrable_domain(&host)?;
+
+    Some(Rank::Domain).filter(|_| registrable_domain(&entry_host) == Some(domain))
+}
+
+fn is_domain(host: &str) -> bool {
+    !host.starts_with('[') && host.parse::<Ipv4Addr>().is_err()
+}
+
+fn host_of(url: &str) -> Option<String> {
+    let rest = url
+        .trim()
+        .split_once("://")
+        .map_or(url.trim(), |(_, rest)| rest);
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+
+    // Keep IPv6 brackets as `Url::host_str` does
+    let host = match host_port.find(']') {
+        Some(end) => &host_port[..=end],
+        None => host_port.split(':').next()?,
+    };
+
+    Some(host.trim_end_matches('.').to_lowercase()).filter(|h| !h.is_empty())
+}
+
+/// Part of the host its owner registered, e.g. `example.co.uk` or `user.github.io`.
+///
+/// Public suffix list includes the shared hosting ones, so tenants of
+/// `github.io` or `herokuapp.com` don't share a domain. Suffixes have none.
+fn registrable_domain(host: &str) -> Option<&str> {
+    psl::domain_str(host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rank_of(entry_url: &str, url: &str) -> Option<Rank> {
+        rank(entry_url, &parse(url).unwrap())
+    }
+
+    #[test]
+    fn exact() {
+        assert_eq!(
+            rank_of("https://example.com/login", "example.com"),
+            Some(Rank::Exact)
+        );
+        assert_eq!(
+            rank_of("user@Example.COM:8080", "https://example.com/"),
+            Some(Rank::Exact)
+        );
+        assert_eq!(rank_of("https://example.com", "example.org"), None);
+    }
+
+    #[test]
+    fn domain() {
+        assert_eq!(
+            rank_of("https://example.com", "login.example.com"),
+            Some(Rank::Domain)
+        );
+        assert_eq!(
+            rank_of("https://www.example.com", "login.example.com"),
+            Some(Rank::Domain)
+        );
+        assert_eq!(rank_of("https://example.com", "example.com.evil.net"), None);
+    }
+
+    #[test]
+    fn shared_suffixes() {
+        assert_eq!(rank_of("https://bob.github.io", "eve.github.io"), None);
+        assert_eq!(
+            rank_of("https://bob.herokuapp.com", "eve.herokuapp.com"),
+            None
+        );
+        assert_eq!(rank_of("https://github.io", "eve.github.io"), None);
+        assert_eq!(
+            rank_of("https://bob.github.io", "www.bob.github.io"),
+            Some(Rank::Domain)
+        );
+    }
+
+    #[test]
+    fn wildcard() {
+        assert_eq!(
+            rank_of("https://*.example.com", "a.b.example.com"),
+            Some(Rank::Wildcard)
+        );
+        assert_eq!(rank_of("https://*.example.com", "example.com"), None);
+        assert_eq!(rank_of("https://*.example.com", "badexample.com"), None);
+    }
+
+    #[test]
+    fn ip() {
+        assert_eq!(
+            rank_of("http://192.168.1.1:8080/", "192.168.1.1"),
+            Some(Rank::Exact)
+        );
+        assert_eq!(rank_of("http://192.168.1.1", "192.168.2.1"), None);
+        assert_eq!(
+            rank_of("http://[::1]:8080", "http://[::1]/"),
+            Some(Rank::Exact)
+        );
+    }
+
+    #[test]
+    fn country_code_second_level() {
+        assert_eq!(
+            rank_of("https://example.co.uk", "login.example.co.uk"),
+            Some(Rank::Domain)
+        );
+        assert_eq!(rank_of("https://bank.co.uk", "evil.co.uk"), None);
+        assert_eq!(rank_of("https://co.uk", "evil.co.uk"), None);
+    }
+}
@@ -4,8 +4,14 @@ use libc::{isatty, tcgetattr, tcsetattr, ECHO, ECHONL, STDIN_FILENO, TCSANOW};
 
 use log::*;
 
+use std::env;
+use std::fs::File;
 use std::io::{self, Read};
 use std::mem::MaybeUninit;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::path::PathBuf;
+use std::sync::Arc;
 
 pub struct Stdin(Option<libc::termios>);
 
@@ -35,6 +41,73 @@ impl Stdin {
     }
 }
 
+/// Master password given by other means than STDIN, which stays free for other uses.
+#[derive(Debug, Clone)]
+pub enum PasswordSource {
+    Fd(RawFd),
+    File(PathBuf),
+    /// Variable name and its value, taken out of the environment right away
+    Env(String, Arc<Pwd>),
+}
+
+impl PasswordSource {
+    /// Removes the variable from the environment, so programs run by `exec` don't inherit it.
+    pub fn from_env(var: String) -> Result<Self, String> {
+        let password =
+            env::var(&var).map_err(|e| format!("Can't read password from ${}. {}", var, e))?;
+
+        env::remove_var(&var);
+
+        Ok(PasswordSource::Env(var, Arc::new(password.into())))
+    }
+
+    pub fn read_password(&self) -> Result<Pwd, String> {
+        let mut password = String::new();
+
+        match self {
+            PasswordSource::Fd(fd) => {
+                info!("read_password() :: FD {}", fd);
+
+                // The descriptor is consumed like a pipe, so it must be open to be owned
+                unsafe {
+                    if libc::fcntl(*fd, libc::F_GETFD) == -1 {
+                        Err(io::Error::last_os_error())
+                    } else {
+                        File::from_raw_fd(*fd).read_to_string(&mut password)
+                    }
+                }
+                .map_err(|e| format!("Can't read password from descriptor {}. {}", fd, e))?;
+            }
+            PasswordSource::File(path) => {
+                info!("read_password() :: FILE {}", path.display());
+
+                let mut file = File::open(path)
+                    .map_err(|e| format!("Can't open `{}`. {}", path.display(), e))?;
+
+                if let Ok(meta) = file.metadata() {
+                    if meta.permissions().mode() & 0o077 != 0 {
+                        werr!(
+                            "Warning: `{}` is accessible by other users, `chmod 600` it.",
+                            path.display()
+                        );
+                    }
+                }
+
+                file.read_to_string(&mut password)
+                    .map_err(|e| format!("Can't read `{}`. {}", path.display(), e))?;
+            }
+            PasswordSource::Env(var, value) => {
+                info!("read_password() :: ENV {}", var);
+                password.push_str(value);
+            }
+        }
+
+        trim_newlines(&mut password);
+
+        Ok(password.into())
+    }
+}
+
 fn new_impl() -> ::std::io::Result<Stdin> {
     unsafe {
         let mut termios = MaybeUninit::uninit();
@@ -1,17 +1,26 @@
-use crate::keyring::Keyring;
+use crate::agent;
+use crate::entry::Entry;
+use crate::keyring::{Backend, Keyring};
+use crate::prompt::Helper;
+use crate::pwd::Pwd;
+use crate::resolve::Resolver;
+use crate::site;
+use crate::stdin::PasswordSource;
 use crate::Result;
 use crate::STDIN;
 
-#[cfg(feature = "clipboard")]
-use clipboard::{ClipboardContext, ClipboardProvider};
-use kdbx4::{CompositeKey, Database, Entry, Kdbx4};
+use kdbx4::{CompositeKey, Database, Kdbx4};
+use once_cell::unsync::OnceCell;
 use skim::prelude::*;
 
 use log::*;
 
 use std::borrow::Cow;
+use std::env;
 use std::io;
-use std::path::Path;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
 
 #[macro_export]
 macro_rules! put {
@@ -40,9 +49,58 @@ macro_rules! werr {
     });
 }
 
-pub fn open_database(dbfile: &Path, keyfile: Option<&Path>, use_keyring: bool) -> Result<Database> {
-    let keyring = if use_keyring {
-        Keyring::from_db_path(dbfile).map(|k| {
+/// Database file with the means to unlock it.
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// Set only when more than one database is used
+    pub label: Option<String>,
+    pub path: PathBuf,
+    pub key_file: Option<PathBuf>,
+    pub use_keyring: bool,
+    pub keyring: Backend,
+    /// How long the keyring keeps the password, forever if not set
+    pub keyring_ttl: Option<Duration>,
+    /// Asks for the password instead of the terminal
+    pub prompt: Option<Helper>,
+    /// Takes the password instead of piped STDIN
+    pub password: Option<PasswordSource>,
+}
+
+/// Entries of every database, taken from the agent if it has them unlocked.
+pub fn open_databases(sources: &[Source]) -> Result<Vec<Vec<Entry>>> {
+    let cached = agent::fetch(sources);
+
+    // Pipe or descriptor can be read only once, so the password is shared among databases
+    let piped = OnceCell::new();
+
+    sources
+        .iter()
+        .zip(cached)
+        .map(|(src, cached)| match cached {
+            Some(entries) => Ok(entries),
+            None => unlock(src, &piped),
+        })
+        .collect()
+}
+
+/// Opens the database and copies its entries out, see [`Entry`].
+pub fn unlock(source: &Source, piped: &OnceCell<Pwd>) -> Result<Vec<Entry>> {
+    let db = open_database(source, piped).map_err(|e| match &source.label {
+        Some(label) => format!("{}: {}", label, e),
+        None => e.to_string(),
+    })?;
+    let db = Rc::new(db);
+
+    let entries = db.entries().iter().map(|e| Entry::new(e, &db)).collect();
+    Ok(entries)
+}
+
+fn open_database(source: &Source, piped: &OnceCell<Pwd>) -> Result<Database> {
+    let dbfile = source.path.as_path();
+    let keyfile = source.key_file.as_deref();
+
+    let keyring = if source.use_keyring {
+        Keyring::from_db_path(dbfile, source.keyring).map(|k| {
             debug!("keyring: {}", k);
             k
         })
@@ -51,7 +109,7 @@ pub fn open_database(dbfile: &Path, keyfile: Option<&Path>, use_keyring: bool) -
     };
 
     // Try to open DB with a key from keyring
-    if let Some(Ok(pwd)) = keyring.as_ref().map(|k| k.get_password()) {
+    if let Some(pwd) = keyring.as_ref().and_then(|k| keyring_password(k, source)) {
         let key = CompositeKey::new(Some(&pwd), keyfile)?;
         if let Ok(db) = Kdbx4::open(dbfile, key) {
             return Ok(db);
@@ -61,26 +119,52 @@ pub fn open_database(dbfile: &Path, keyfile: Option<&Path>, use_keyring: bool) -
         let _ = keyring.as_ref().map(|k| k.delete_password());
     }
 
+    // Try read password from the given descriptor, file or variable
+    if let Some(from) = &source.password {
+        let pwd = piped.get_or_try_init(|| from.read_password())?;
+        let key = CompositeKey::new(Some(&**pwd), keyfile)?;
+        let db = Kdbx4::open(dbfile, key)?;
+        return Ok(db);
+    }
+
     // Try read password from pipe
-    if !is_tty(io::stdin()) {
-        let pwd = STDIN.read_password();
-        let key = CompositeKey::new(Some(&pwd), keyfile)?;
+    if !is_tty(io::stdin()) && source.prompt.is_none() {
+        let pwd = piped.get_or_init(|| STDIN.read_password());
+        let key = CompositeKey::new(Some(&**pwd), keyfile)?;
         let db = Kdbx4::open(dbfile, key)?;
         return Ok(db);
     }
 
-    // Allow multiple attempts to enter the password from TTY
+    // Allow multiple attempts to enter the password from TTY or a helper
     let mut att = 3;
+    let mut error = None;
     loop {
-        put!("Password:");
+        let pwd = match (&source.prompt, &source.label) {
+            (Some(helper), label) => helper.read_password(
+                &format!(
+                    "Master password for {}",
+                    label.as_deref().unwrap_or(&dbfile.to_string_lossy())
+                ),
+                error.as_deref(),
+            )?,
+            (None, Some(label)) => {
+                put!("Password for {}:", label);
+                STDIN.read_password()
+            }
+            (None, None) => {
+                put!("Password:");
+                STDIN.read_password()
+            }
+        };
 
-        let pwd = STDIN.read_password();
         let key = CompositeKey::new(Some(&pwd), keyfile)?;
         let db = Kdbx4::open(dbfile, key);
 
         // If opened successfully store the password
         if db.is_ok() {
-            let _ = keyring.as_ref().map(|k| k.set_password(&pwd));
+            let _ = keyring
+                .as_ref()
+                .map(|k| k.set_password(&pwd, source.keyring_ttl));
         }
 
         att -= 1;
@@ -89,17 +173,185 @@ pub fn open_database(dbfile: &Path, keyfile: Option<&Path>, use_keyring: bool) -
             break db.map_err(From::from);
         }
 
-        wout!("{} attempt(s) left.", att);
+        match source.prompt {
+            Some(_) => error = Some(format!("Wrong password, {} attempt(s) left.", att)),
+            None => wout!("{} attempt(s) left.", att),
+        }
+    }
+}
+
+/// Takes the password from the keyring moving it from the legacy slot if needed.
+fn keyring_password(keyring: &Keyring, source: &Source) -> Option<Pwd> {
+    if let Ok(pwd) = keyring.get_password() {
+        // Stored before the TTL was configured, it would be kept for good otherwise
+        if source.keyring_ttl.is_some() && !keyring.has_expiry() {
+            debug!("setting TTL of the stored password: {}", keyring);
+            let _ = keyring.set_password(&pwd, source.keyring_ttl);
+        }
+
+        return Some(pwd);
+    }
+
+    let legacy = Keyring::legacy(&source.path, source.keyring)?;
+    let pwd = legacy.get_password().ok()?;
+
+    debug!("moving password from the legacy keyring slot: {}", legacy);
+
+    if keyring.set_password(&pwd, source.keyring_ttl).is_ok() {
+        let _ = legacy.delete_password();
+    }
+
+    Some(pwd)
+}
+
+/// Parses `90s`, `30m`, `8h`, `1d` or just seconds.
+pub fn parse_duration(val: &str) -> Result<Duration> {
+    let val = val.trim();
+    let (num, unit) = val.split_at(val.find(|c: char| !c.is_ascii_digit()).unwrap_or(val.len()));
+
+    let secs = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+
+    match num.parse::<u64>() {
+        Ok(num) if num > 0 && secs > 0 => Ok(Duration::from_secs(num.saturating_mul(secs))),
+        _ => Err(format!(
+            "Invalid duration `{}`. Use e.g. `90s`, `30m`, `8h` or `1d`.",
+            val
+        )
+        .into()),
+    }
+}
+
+/// Entries of every opened database.
+pub struct Entries<'a> {
+    labels: Vec<Option<&'a str>>,
+    entries: &'a [Vec<Entry>],
+}
+
+impl<'a> Entries<'a> {
+    pub fn new(dbs: &'a [Vec<Entry>], sources: &'a [Source]) -> Self {
+        Entries {
+            labels: sources.iter().map(|s| s.label.as_deref()).collect(),
+            entries: dbs,
+        }
+    }
+
+    /// Entries of a single database, e.g. to resolve references within it.
+    pub fn of(&'a self, db: usize) -> &'a [Entry] {
+        &self.entries[db]
+    }
+
+    pub fn iter(&'a self) -> impl Iterator<Item = (usize, &'a Entry)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .flat_map(|(db, entries)| entries.iter().map(move |e| (db, e)))
+    }
+
+    /// Same as `Database::find` but for all databases.
+    pub fn find(&'a self, query: &str) -> Vec<(usize, &'a Entry)> {
+        let query = query.to_lowercase();
+
+        self.iter()
+            .filter(|(_, e)| e.title().to_lowercase().starts_with(&query))
+            .collect()
+    }
+
+    /// Title prefix or, if `by_url`, URL search (see [`Entries::find_url`]).
+    pub fn search(&'a self, query: &str, by_url: bool) -> Result<Vec<(usize, &'a Entry)>> {
+        match by_url {
+            true => self.find_url(query, site::Rank::Wildcard),
+            false => Ok(self.find(query)),
+        }
+    }
+
+    /// Same as [`Entries::search`], but for taking the entry without asking the user.
+    ///
+    /// Only exact URL matches count, a sibling subdomain may belong to somebody else.
+    pub fn search_unattended(
+        &'a self,
+        query: &str,
+        by_url: bool,
+    ) -> Result<Vec<(usize, &'a Entry)>> {
+        match by_url {
+            true => self.find_url(query, site::Rank::Exact),
+            false => Ok(self.find(query)),
+        }
+    }
+
+    /// Entries whose URL or `KP2A_URL*` fields match the URL best, but not worse than `worst`.
+    pub fn find_url(&'a self, url: &str, worst: site::Rank) -> Result<Vec<(usize, &'a Entry)>> {
+        let url = site::parse(url)?;
+
+        let ranked = self
+            .iter()
+            .filter_map(|(db, e)| {
+                let resolver = Resolver::new(self.of(db));
+
+                entry_urls(e)
+                    .iter()
+                    .filter_map(|field| resolver.field(e, Some(field)).ok())
+                    .filter_map(|val| site::rank(&val, &url))
+                    .filter(|&rank| rank <= worst)
+                    .min()
+                    .map(|rank| (rank, (db, e)))
+            })
+            .collect::<Vec<_>>();
+
+        let best = ranked.iter().map(|(rank, _)| *rank).min();
+        debug!("best URL match: {:?}", best);
+
+        Ok(ranked
+            .into_iter()
+            .filter(|(rank, _)| Some(*rank) == best)
+            .map(|(_, found)| found)
+            .collect())
+    }
+
+    pub fn label(&self, db: usize) -> Option<&'a str> {
+        self.labels[db]
     }
+
+    /// Entry's path prefixed with database's label, e.g. `team:/Root/github`.
+    pub fn path(&'a self, db: usize, entry: &'a Entry, hide_groups: bool) -> String {
+        match self.labels[db] {
+            Some(label) => format!("{}:{}", label, entry_path(entry, hide_groups)),
+            None => entry_path(entry, hide_groups),
+        }
+    }
+}
+
+/// `URL` and KeePassXC's additional URL fields `KP2A_URL`, `KP2A_URL_1`, ...
+fn entry_urls(entry: &Entry) -> Vec<String> {
+    let mut fields = vec!["url".to_owned()];
+
+    if entry.prop("KP2A_URL").is_some() {
+        fields.push("KP2A_URL".to_owned());
+    }
+
+    fields.extend(
+        (1..)
+            .map(|n| format!("KP2A_URL_{}", n))
+            .take_while(|f| entry.prop(f).is_some()),
+    );
+
+    fields
 }
 
+/// Lets user pick one of `items`, usually all of `entries`.
 pub fn skim<'a>(
-    entries: &'a [Entry<'a>],
+    entries: &'a Entries<'a>,
+    items: Vec<(usize, &'a Entry)>,
     query: Option<&'a str>,
     hide_groups: bool,
     show_preview: bool,
     full_screen: bool,
-) -> Option<&'a Entry<'a>> {
+) -> Option<(usize, &'a Entry)> {
     let opts = SkimOptionsBuilder::default()
         .multi(false)
         .reverse(true)
@@ -121,15 +373,11 @@ pub fn skim<'a>(
 
     let (tx, rx): (SkimItemSender, SkimItemReceiver) = unbounded();
 
-    entries
+    items
         .iter()
         .enumerate()
-        .map(|(idx, e)| {
-            let title = if hide_groups {
-                e.title().to_owned()
-            } else {
-                format!("/{}/{}", e.group(), e.title())
-            };
+        .map(|(idx, &(db, e))| {
+            let title = entries.path(db, e, hide_groups);
 
             let props = if show_preview {
                 Some(format!("{}", e))
@@ -153,12 +401,92 @@ pub fn skim<'a>(
                     .as_ref()
                     .as_any()
                     .downcast_ref::<EntryItem>()
-                    .map(|ei| &entries[ei.idx])
+                    .map(|ei| items[ei.idx])
             }
         })
         .unwrap()
 }
 
+/// Returns a value of the entry's field.
+///
+/// Standard fields are matched case-insensitively, anything else
+/// is looked up as a custom string field. No field means password.
+pub fn entry_field(entry: &Entry, field: Option<&str>) -> Result<String> {
+    let field = match field {
+        None => return Ok(entry.password()?.to_string()),
+        Some(f) => f,
+    };
+
+    let prop = match &*field.to_lowercase() {
+        "password" | "pass" => return Ok(entry.password()?.to_string()),
+        "title" => "Title",
+        "username" | "user" => "UserName",
+        "url" => "URL",
+        "notes" => "Notes",
+        _ => field,
+    };
+
+    match entry.prop(prop) {
+        // Only the password is decrypted by kdbx4; other protected fields are masked
+        Some("(protected)") => {
+            Err(format!("Field `{}` is protected and can't be read.", field).into())
+        }
+        Some(val) => Ok(unescape_xml(val)),
+        None => Err(format!("No field `{}` in `{}`.", field, entry.title()).into()),
+    }
+}
+
+/// kdbx4 hands out field values as they are in the XML, i.e. `&amp;` instead of `&`.
+fn unescape_xml(val: &str) -> String {
+    let mut result = String::with_capacity(val.len());
+    let mut rest = val;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let chr = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            ent if ent.starts_with("#x") => u32::from_str_radix(&ent[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            ent if ent.starts_with('#') => ent[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        };
+
+        match chr {
+            Some(chr) => {
+                result.push(chr);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+pub fn entry_path(entry: &Entry, hide_groups: bool) -> String {
+    if hide_groups {
+        entry.title().to_owned()
+    } else {
+        format!("/{}/{}", entry.group(), entry.title())
+    }
+}
+
 struct EntryItem {
     idx: usize,
     title: String,
@@ -179,19 +507,14 @@ impl SkimItem for EntryItem {
     }
 }
 
-#[cfg(feature = "clipboard")]
-pub fn set_clipboard(val: Option<String>) -> Result<()> {
-    ClipboardProvider::new()
-        .and_then(|mut ctx: ClipboardContext| ctx.set_contents(val.unwrap_or_default()))
-        .map_err(|e| {
-            warn!("could not set the clipboard: {}", e);
-            e
-        })
-}
-
-#[cfg(not(feature = "clipboard"))]
-pub fn set_clipboard(_: Option<String>) -> Result<()> {
-    Err("Feature clipboard is not available.".into())
+/// Path of the program if it's on `PATH`.
+pub fn find_program(name: &str) -> Option<PathBuf> {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
 }
 
 pub fn is_tty(fd: impl std::os::unix::io::AsRawFd) -> bool {